@@ -0,0 +1,17 @@
+// A minimal server answering `200 ok` over HTTP/1.1 and h2c (HTTP/2 with prior knowledge).
+// Used to benchmark the load generator itself, without a real service in the way.
+// Usage: cargo run --release --example local_server -- <port>
+use tokio::net::TcpListener;
+
+// Shared with the in-process benchmark of the load generator's own tests.
+#[path = "../src/local_server/mod.rs"]
+mod local_server;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let port = std::env::args().nth(1).unwrap_or_else(|| "8080".to_string());
+    let listener = TcpListener::bind(format!("127.0.0.1:{}", port)).await?;
+    println!("Listening on http://127.0.0.1:{}", port);
+    local_server::serve(listener).await?;
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use std::net::Ipv6Addr;
+
+use hyper::Uri;
+
+use crate::errors::LoadGenError;
+
+/// The service-under-test, parsed from the address given on the CLI.
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub uri: Uri,
+    // IPv6 literals are kept without their surrounding brackets.
+    pub host: String,
+    pub port: u16,
+}
+
+/// Accepts either <endpoint>:<port> (assumed to be http) or a full URL including scheme (http or https), path and query.
+/// IPv6 literals need to be bracketed e.g. `[::1]:8080`. Without a port, the default port of the scheme is used.
+pub fn parse_address(address: &str) -> Result<Target, LoadGenError> {
+    let address = if address.contains("://") {
+        address.to_string()
+    } else {
+        format!("http://{}", address)
+    };
+    // The scheme and authority are checked part by part before parsing the whole URI,
+    // so that a malformed part gets its own error rather than a generic invalid URI.
+    let (scheme, rest) = address.split_once("://").ok_or_else(|| LoadGenError::InvalidUriError(address.clone()))?;
+    let scheme = scheme.to_lowercase();
+    let default_port = match scheme.as_str() {
+        "http" => 80,
+        "https" => 443,
+        _ => return Err(LoadGenError::UnsupportedSchemeError(scheme)),
+    };
+
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let (host, port) = split_authority(authority)?;
+    if host.is_empty() {
+        return Err(LoadGenError::MissingHostError(address));
+    }
+    let host = validate_host(host)?;
+    let port = match port {
+        Some(port) => validate_port(port)?,
+        None => default_port,
+    };
+
+    let uri = address.parse::<Uri>().map_err(|_| LoadGenError::InvalidUriError(address.clone()))?;
+    Ok(Target { uri, host, port })
+}
+
+fn validate_host(host: &str) -> Result<String, LoadGenError> {
+    if let Some(ipv6) = host.strip_prefix('[').and_then(|host| host.strip_suffix(']')) {
+        if ipv6.parse::<Ipv6Addr>().is_err() {
+            return Err(LoadGenError::InvalidHostError(host.to_string()));
+        }
+        return Ok(ipv6.to_string());
+    }
+    Ok(host.to_string())
+}
+
+// Splits the host from the (optional) port, ignoring any userinfo. Only a bracketed IPv6 literal may contain a ':'.
+fn split_authority(authority: &str) -> Result<(&str, Option<&str>), LoadGenError> {
+    let host_and_port = authority.rsplit('@').next().unwrap_or(authority);
+    let invalid_host = || LoadGenError::InvalidHostError(host_and_port.to_string());
+    if host_and_port.starts_with('[') {
+        let end = host_and_port.find(']').ok_or_else(invalid_host)?;
+        let (host, rest) = host_and_port.split_at(end + 1);
+        return match rest.strip_prefix(':') {
+            Some(port) => Ok((host, Some(port))),
+            None if rest.is_empty() => Ok((host, None)),
+            None => Err(invalid_host()),
+        };
+    }
+    match host_and_port.split_once(':') {
+        // More than one ':' is an IPv6 literal without its brackets.
+        Some((_, port)) if port.contains(':') => Err(invalid_host()),
+        Some((host, port)) => Ok((host, Some(port))),
+        None => Ok((host_and_port, None)),
+    }
+}
+
+fn validate_port(port: &str) -> Result<u16, LoadGenError> {
+    match port.parse::<u16>() {
+        Ok(port) if port > 0 => Ok(port),
+        _ => Err(LoadGenError::InvalidPortError(port.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_to_the_port_of_the_scheme() {
+        let target = parse_address("localhost").unwrap();
+        assert_eq!(target.host, "localhost");
+        assert_eq!(target.port, 80);
+        assert_eq!(target.uri.scheme_str(), Some("http"));
+
+        let target = parse_address("https://example.com/").unwrap();
+        assert_eq!(target.host, "example.com");
+        assert_eq!(target.port, 443);
+    }
+
+    #[test]
+    fn parses_an_explicit_port() {
+        let target = parse_address("localhost:8080").unwrap();
+        assert_eq!(target.host, "localhost");
+        assert_eq!(target.port, 8080);
+    }
+
+    #[test]
+    fn keeps_the_path_and_query() {
+        let target = parse_address("http://localhost:8080/api/echo?id=1").unwrap();
+        assert_eq!(target.port, 8080);
+        assert_eq!(target.uri.path(), "/api/echo");
+        assert_eq!(target.uri.query(), Some("id=1"));
+    }
+
+    #[test]
+    fn parses_bracketed_ipv6() {
+        let target = parse_address("[::1]:8080").unwrap();
+        assert_eq!(target.host, "::1");
+        assert_eq!(target.port, 8080);
+
+        let target = parse_address("http://[::1]/").unwrap();
+        assert_eq!(target.host, "::1");
+        assert_eq!(target.port, 80);
+    }
+
+    #[test]
+    fn rejects_unbracketed_ipv6() {
+        assert!(matches!(parse_address("::1:8080"), Err(LoadGenError::InvalidHostError(_))));
+        assert!(matches!(parse_address("http://::1/"), Err(LoadGenError::InvalidHostError(_))));
+    }
+
+    #[test]
+    fn rejects_invalid_ipv6() {
+        assert!(matches!(parse_address("[::zz]:8080"), Err(LoadGenError::InvalidHostError(_))));
+        assert!(matches!(parse_address("[::1:8080"), Err(LoadGenError::InvalidHostError(_))));
+    }
+
+    #[test]
+    fn rejects_invalid_ports() {
+        for address in ["localhost:0", "localhost:99999", "http://host:abc", "localhost:"] {
+            assert!(matches!(parse_address(address), Err(LoadGenError::InvalidPortError(_))), "{}", address);
+        }
+    }
+
+    #[test]
+    fn rejects_a_missing_host() {
+        assert!(matches!(parse_address("http://:8080/"), Err(LoadGenError::MissingHostError(_))));
+        assert!(matches!(parse_address("http://"), Err(LoadGenError::MissingHostError(_))));
+    }
+
+    #[test]
+    fn rejects_unsupported_schemes() {
+        assert!(matches!(parse_address("ftp://localhost:21"), Err(LoadGenError::UnsupportedSchemeError(_))));
+    }
+}
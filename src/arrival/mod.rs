@@ -0,0 +1,155 @@
+use std::time::Duration;
+
+use clap::ValueEnum;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// How the gaps between consecutive requests are distributed. The mean gap is always `1 / rate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Arrival {
+    /// Evenly spaced requests.
+    Constant,
+    /// Gaps drawn uniformly between zero and twice the mean gap.
+    Uniform,
+    /// Exponentially distributed gaps, i.e. a Poisson process, like independent users arriving.
+    Poisson,
+}
+
+/// Generates the arrival times of requests over the course of a run.
+/// The RNG is seeded, so a run can be reproduced by reusing its seed.
+pub struct ArrivalSchedule {
+    arrival: Arrival,
+    rng: StdRng,
+    // Time since the start of the run up to which arrivals have been generated.
+    covered: Duration,
+    // What is left of the gap until the next request, in unit-rate time (i.e. in requests, whatever the rate).
+    remaining_gap: f64,
+}
+
+impl ArrivalSchedule {
+    pub fn new(arrival: Arrival, seed: u64) -> Self {
+        ArrivalSchedule {
+            arrival,
+            rng: StdRng::seed_from_u64(seed),
+            covered: Duration::ZERO,
+            // The first request arrives as soon as the rate is above zero.
+            remaining_gap: 0f64,
+        }
+    }
+
+    /// Arrival times (as offsets from the start of the run) of every request before `until`, at the given (possibly fractional) rate.
+    /// The rate applies from where the previous call left off.
+    pub fn arrivals_until(&mut self, until: Duration, rate: f64) -> Vec<Duration> {
+        let mut arrivals = vec![];
+        if rate <= 0f64 {
+            // Nothing arrives while the rate is zero, the rest of the gap is used up once the rate picks up again.
+            self.covered = self.covered.max(until);
+            return arrivals;
+        }
+        // Gaps are used up at whatever the rate currently is, so a new rate takes effect right away rather than
+        // one (possibly very long) gap later e.g. when ramping up from zero.
+        while self.covered < until {
+            let left = until - self.covered;
+            let expected_requests = rate * left.as_secs_f64();
+            if self.remaining_gap >= expected_requests {
+                self.remaining_gap -= expected_requests;
+                self.covered = until;
+                break;
+            }
+            // Never more than `left`, so even a tiny rate can't overflow the duration.
+            self.covered += Duration::from_secs_f64(self.remaining_gap / rate).min(left);
+            arrivals.push(self.covered);
+            self.remaining_gap = self.next_gap();
+        }
+        arrivals
+    }
+
+    // The gap to the next request in unit-rate time, i.e. with a mean of one request.
+    fn next_gap(&mut self) -> f64 {
+        match self.arrival {
+            Arrival::Constant => 1f64,
+            Arrival::Uniform => self.rng.gen_range(0f64..2f64),
+            // Inverse transform sampling of the exponential distribution. `1 - u` avoids ln(0).
+            Arrival::Poisson => -(1f64 - self.rng.gen::<f64>()).ln(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::parse_profile;
+
+    const SLOT: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn spaces_constant_arrivals_evenly() {
+        let mut schedule = ArrivalSchedule::new(Arrival::Constant, 0);
+        let arrivals = schedule.arrivals_until(Duration::from_millis(10), 1000f64);
+        assert_eq!(arrivals, (0..10).map(Duration::from_millis).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn supports_fractional_rates() {
+        let mut schedule = ArrivalSchedule::new(Arrival::Constant, 0);
+        let mut arrivals = vec![];
+        let mut until = Duration::ZERO;
+        while until < Duration::from_millis(9500) {
+            until += SLOT;
+            arrivals.extend(schedule.arrivals_until(until, 0.5));
+        }
+        // Every 2s, give or take rounding over the many slots in between.
+        assert_eq!(arrivals.len(), 5);
+        assert!(arrivals[1].abs_diff(Duration::from_secs(2)) < SLOT, "{:?}", arrivals[1]);
+    }
+
+    #[test]
+    fn applies_a_new_rate_right_away() {
+        let mut schedule = ArrivalSchedule::new(Arrival::Constant, 0);
+        assert_eq!(schedule.arrivals_until(Duration::from_millis(500), 1f64), vec![Duration::ZERO]);
+        // Half of the 1s gap is left, which only takes half a millisecond at 1000 rps.
+        let arrivals = schedule.arrivals_until(Duration::from_millis(600), 1000f64);
+        assert_eq!(arrivals.len(), 100);
+        assert!(arrivals[0] > Duration::from_millis(500) && arrivals[0] < Duration::from_millis(501));
+    }
+
+    #[test]
+    fn follows_a_ramp_from_zero() {
+        let profile = parse_profile("0-100rps:60s").unwrap();
+        let mut schedule = ArrivalSchedule::new(Arrival::Constant, 0);
+        let mut arrivals = 0;
+        let mut slot_start = Duration::ZERO;
+        while slot_start < Duration::from_secs(60) {
+            arrivals += schedule.arrivals_until(slot_start + SLOT, profile.rate_at(slot_start)).len();
+            slot_start += SLOT;
+        }
+        // The area under the ramp: 100rps / 2 over 60s.
+        assert!((2990..=3001).contains(&arrivals), "{} arrivals", arrivals);
+    }
+
+    #[test]
+    fn resumes_after_a_zero_rate() {
+        let mut schedule = ArrivalSchedule::new(Arrival::Constant, 0);
+        assert!(schedule.arrivals_until(Duration::from_secs(1), 0f64).is_empty());
+        assert_eq!(schedule.arrivals_until(Duration::from_millis(1001), 1000f64), vec![Duration::from_secs(1)]);
+    }
+
+    #[test]
+    fn handles_tiny_rates() {
+        let mut schedule = ArrivalSchedule::new(Arrival::Poisson, 0);
+        assert_eq!(schedule.arrivals_until(Duration::from_secs(1), 1e-300), vec![Duration::ZERO]);
+        assert!(schedule.arrivals_until(Duration::from_secs(2), 1e-300).is_empty());
+    }
+
+    #[test]
+    fn reproduces_random_arrivals_from_the_seed() {
+        for arrival in [Arrival::Uniform, Arrival::Poisson] {
+            let mut first = ArrivalSchedule::new(arrival, 42);
+            let mut second = ArrivalSchedule::new(arrival, 42);
+            let arrivals = first.arrivals_until(Duration::from_secs(10), 1000f64);
+            assert_eq!(arrivals, second.arrivals_until(Duration::from_secs(10), 1000f64));
+            // The mean gap is still `1 / rate`.
+            assert!((9500..=10500).contains(&arrivals.len()), "{:?}: {} arrivals", arrival, arrivals.len());
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use hyper::rt::{Read, ReadBuf, ReadBufCursor, Write};
+use hyper::Uri;
+use hyper_rustls::HttpsConnector;
+use hyper_util::client::legacy::connect::{Connected, Connection, HttpConnector};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+use tower_service::Service;
+
+type InnerConnector = HttpsConnector<HttpConnector>;
+type InnerStream = <InnerConnector as Service<Uri>>::Response;
+type InnerError = <InnerConnector as Service<Uri>>::Error;
+
+// Size of the buffer reads go through, to see how much each read returned.
+const READ_BUFFER_SIZE: usize = 16 * 1024;
+
+/// When a connection was established.
+/// Attached to every response received over the connection, so a request can tell whether it had to wait for it.
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectionInfo {
+    pub established_at: Instant,
+}
+
+/// Connection-level counters, updated by the connector and its connections while a run is going.
+#[derive(Debug, Default)]
+pub struct ConnectionCounters {
+    opened: AtomicU64,
+    failed: AtomicU64,
+    closed: AtomicU64,
+    closed_by_peer: AtomicU64,
+    connect_micros: AtomicU64,
+}
+
+impl ConnectionCounters {
+    pub fn opened(&self) -> u64 {
+        self.opened.load(Ordering::Relaxed)
+    }
+
+    pub fn failed(&self) -> u64 {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    pub fn closed(&self) -> u64 {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    pub fn closed_by_peer(&self) -> u64 {
+        self.closed_by_peer.load(Ordering::Relaxed)
+    }
+
+    /// Mean time taken to establish a connection (TCP and TLS handshakes), in microseconds.
+    pub fn mean_connect_micros(&self) -> f64 {
+        match self.opened() {
+            0 => 0f64,
+            opened => self.connect_micros.load(Ordering::Relaxed) as f64 / opened as f64,
+        }
+    }
+}
+
+/// Wraps the (TLS) connector to time and count connections, optionally capping how many are open at once.
+#[derive(Clone)]
+pub struct TimedConnector {
+    inner: InnerConnector,
+    counters: Arc<ConnectionCounters>,
+    // Held by every open connection. New connections wait for a permit once the cap is reached.
+    connection_limit: Option<Arc<Semaphore>>,
+}
+
+impl TimedConnector {
+    pub fn new(inner: InnerConnector, counters: Arc<ConnectionCounters>, max_connections: Option<usize>) -> Self {
+        TimedConnector {
+            inner,
+            counters,
+            connection_limit: max_connections.map(|max_connections| Arc::new(Semaphore::new(max_connections))),
+        }
+    }
+}
+
+impl Service<Uri> for TimedConnector {
+    type Response = TimedConnection;
+    type Error = InnerError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let connecting = self.inner.call(uri);
+        let counters = Arc::clone(&self.counters);
+        let connection_limit = self.connection_limit.clone();
+        Box::pin(async move {
+            // The semaphore is never closed, so acquiring only fails if it was.
+            let permit = match connection_limit {
+                Some(connection_limit) => Some(connection_limit.acquire_owned().await?),
+                None => None,
+            };
+            // Covers the TCP (and TLS) handshakes.
+            let started_at = Instant::now();
+            let stream = match connecting.await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    counters.failed.fetch_add(1, Ordering::Relaxed);
+                    return Err(e);
+                }
+            };
+            let established_at = Instant::now();
+            counters.opened.fetch_add(1, Ordering::Relaxed);
+            counters.connect_micros.fetch_add(established_at.duration_since(started_at).as_micros() as u64, Ordering::Relaxed);
+            Ok(TimedConnection {
+                inner: stream,
+                info: ConnectionInfo { established_at },
+                counters,
+                read_buffer: vec![0; READ_BUFFER_SIZE].into_boxed_slice(),
+                closed_by_peer: false,
+                _permit: permit,
+            })
+        })
+    }
+}
+
+/// A connection that hands its `ConnectionInfo` to the client, which attaches it to every response.
+/// It also notices when the peer closes it: a read hits the end of the stream or fails.
+pub struct TimedConnection {
+    inner: InnerStream,
+    info: ConnectionInfo,
+    counters: Arc<ConnectionCounters>,
+    read_buffer: Box<[u8]>,
+    closed_by_peer: bool,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl TimedConnection {
+    fn mark_closed_by_peer(&mut self) {
+        if !self.closed_by_peer {
+            self.closed_by_peer = true;
+            self.counters.closed_by_peer.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Drop for TimedConnection {
+    fn drop(&mut self) {
+        self.counters.closed.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Connection for TimedConnection {
+    fn connected(&self) -> Connected {
+        // Extras are chained, so the `HttpInfo` of the TCP connector is kept as well.
+        self.inner.connected().extra(self.info)
+    }
+}
+
+impl Read for TimedConnection {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, mut buf: ReadBufCursor<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        // Reads go through our own buffer, as the cursor doesn't tell how much was read into it.
+        let len = buf.remaining().min(this.read_buffer.len());
+        if len == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        let mut read_buffer = ReadBuf::new(&mut this.read_buffer[..len]);
+        match Pin::new(&mut this.inner).poll_read(cx, read_buffer.unfilled()) {
+            Poll::Ready(Ok(())) => {
+                let filled = read_buffer.filled();
+                // Reading nothing into a non-empty buffer means the peer closed the connection.
+                if filled.is_empty() {
+                    this.mark_closed_by_peer();
+                } else {
+                    buf.put_slice(filled);
+                }
+                Poll::Ready(Ok(()))
+            }
+            Poll::Ready(Err(e)) => {
+                this.mark_closed_by_peer();
+                Poll::Ready(Err(e))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl Write for TimedConnection {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_write_vectored(mut self: Pin<&mut Self>, cx: &mut Context<'_>, bufs: &[io::IoSlice<'_>]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+}
\ No newline at end of file
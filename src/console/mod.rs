@@ -0,0 +1,20 @@
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Set once at startup, before anything is printed.
+static DIAGNOSTICS_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Sends every diagnostic (startup info, progress lines, warnings) to stderr from now on,
+/// so that stdout only carries the report e.g. for CI to parse the JSON summary.
+pub fn diagnostics_to_stderr() {
+    DIAGNOSTICS_TO_STDERR.store(true, Ordering::Relaxed);
+}
+
+/// Prints a line that isn't part of the report. It goes to stdout, unless stdout is reserved for the report.
+pub fn diagnostic(message: impl Display) {
+    if DIAGNOSTICS_TO_STDERR.load(Ordering::Relaxed) {
+        eprintln!("{}", message);
+    } else {
+        println!("{}", message);
+    }
+}
@@ -1,66 +1,560 @@
+use std::net::SocketAddr;
 use std::sync::Arc;
-use std::time::Instant;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+use clap::ValueEnum;
 use http_body_util::{BodyExt, Full};
-use hyper::{Method, Request};
+use hyper::{Method, Request, Uri, Version};
+use hyper::header::{HeaderName, HeaderValue};
 use hyper::body::Bytes;
 use hyper_util::client::legacy::Client;
-use hyper_util::client::legacy::connect::HttpConnector;
-use tokio::sync::mpsc::{Sender, UnboundedSender};
-use tokio::sync::Mutex;
-use tokio::time::Interval;
+use hyper_util::client::legacy::connect::HttpInfo;
+use rand::Rng;
+use tokio::sync::mpsc::Sender;
+use tokio::task::{AbortHandle, JoinError, JoinHandle};
+use tokio::time::{sleep, sleep_until, timeout, Instant};
+
+use crate::arrival::ArrivalSchedule;
+use crate::connection::{ConnectionInfo, TimedConnector};
+use crate::console::diagnostic;
+use crate::errors::{LoadGenError, TransportErrorKind};
+use crate::profile::RateProfile;
+
+// Granularity of the open-loop scheduler. Each pass dispatches the requests due within the next slot,
+// which then sleep until their exact intended start.
+const SCHEDULER_SLOT: Duration = Duration::from_millis(1);
+
+/// The pooled client shared by every request. `http://` targets are dialed over plain TCP, `https://` targets over TLS.
+pub type LoadGenClient = Client<TimedConnector, Full<Bytes>>;
+
+/// How requests are spread over the clients of a `ClientSet`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ConnectionStrategy {
+    /// Each client in turn.
+    RoundRobin,
+    /// A uniformly random client for every request.
+    Random,
+}
+
+/// Independent clients, each with its own connection pool. hyper-util multiplexes every HTTP/2 request to a host
+/// over a single connection, so N clients keep N distinct connections e.g. to reach N backends behind an L4 load balancer.
+pub struct ClientSet {
+    clients: Vec<LoadGenClient>,
+    strategy: ConnectionStrategy,
+    next: AtomicUsize,
+}
+
+impl ClientSet {
+    pub fn new(clients: Vec<LoadGenClient>, strategy: ConnectionStrategy) -> Self {
+        assert!(!clients.is_empty(), "at least one client is needed!");
+        ClientSet { clients, strategy, next: AtomicUsize::new(0) }
+    }
+
+    /// Picks the client for the next request, along with its index.
+    fn pick(&self) -> (usize, &LoadGenClient) {
+        let index = match self.strategy {
+            ConnectionStrategy::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed) % self.clients.len(),
+            ConnectionStrategy::Random => rand::thread_rng().gen_range(0..self.clients.len()),
+        };
+        (index, &self.clients[index])
+    }
+}
+
+/// Which HTTP versions the client speaks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolMode {
+    /// HTTP/1.1 only.
+    Http1,
+    /// HTTP/2 only. Prior knowledge (h2c) for `http://`, ALPN `h2` for `https://`.
+    Http2,
+    /// HTTP/2 when the server offers it via ALPN, otherwise HTTP/1.1. Plain `http://` always uses HTTP/1.1.
+    Auto,
+}
+
+/// Timings captured for a single request, in microseconds.
+/// `latency` is measured from the request's _intended_ send time, `service_time` from when it was actually sent.
+#[derive(Debug, Clone, Copy)]
+pub struct RequestTiming {
+    pub latency_micros: u64,
+    pub service_time_micros: u64,
+    pub phases: PhaseTimings,
+    pub bytes_received: u64,
+    // Sent over a pooled connection that was already open, rather than one established for it.
+    pub reused_connection: bool,
+    // The protocol version the response was received over.
+    pub version: Version,
+    // Local address of the connection the response was received over, which identifies the connection.
+    pub connection: Option<SocketAddr>,
+    // Index of the client (in the `ClientSet`) the request was sent with.
+    pub client: usize,
+}
+
+/// Consecutive phases of a single request, in microseconds. Together they add up to its latency.
+#[derive(Debug, Clone, Copy)]
+pub struct PhaseTimings {
+    // From the intended send time until the request was actually sent.
+    pub queue_wait_micros: u64,
+    // Waiting for a new connection to be established. Zero when a pooled connection was reused.
+    pub connect_micros: u64,
+    // From having a connection until the response headers arrived.
+    pub headers_micros: u64,
+    // From the response headers until the first body byte arrived.
+    pub first_byte_micros: u64,
+    // From the first body byte until the body was fully received.
+    pub body_micros: u64,
+}
+
+impl PhaseTimings {
+    pub const NAMES: [&'static str; 5] = ["queue wait", "connect", "headers", "first byte", "body"];
+
+    /// Phase durations in the same order as `NAMES`.
+    pub fn values(&self) -> [u64; 5] {
+        [self.queue_wait_micros, self.connect_micros, self.headers_micros, self.first_byte_micros, self.body_micros]
+    }
+
+    /// From the actual send until the response headers (the first bytes of the response) arrived.
+    pub fn time_to_first_byte_micros(&self) -> u64 {
+        self.connect_micros + self.headers_micros
+    }
+}
+
+/// What we learn from a response that was fully received.
+struct ResponseInfo {
+    status: u16,
+    version: Version,
+    // When the connection the response came over was established, if it was timed by our connector.
+    connection_established_at: Option<Instant>,
+    headers_received: Instant,
+    first_byte_received: Instant,
+    bytes_received: u64,
+    connection: Option<SocketAddr>,
+}
+
+/// Everything needed to build and send each request of a test run.
+pub struct RequestTemplate {
+    uri: Uri,
+    method: Method,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    // `Bytes` is reference counted, so each request shares the same body buffer.
+    body: Bytes,
+    timeout: Duration,
+}
+
+impl RequestTemplate {
+    pub fn new(uri: Uri, method: Method, headers: Vec<(HeaderName, HeaderValue)>, body: Bytes, timeout: Duration) -> Self {
+        RequestTemplate { uri, method, headers, body, timeout }
+    }
+
+    fn build_request(&self) -> Request<Full<Bytes>> {
+        let mut builder = Request::builder()
+            .method(self.method.clone())
+            .uri(self.uri.clone());
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+        builder
+            .body(Full::new(self.body.clone()))
+            .expect("errors constructing request!")
+    }
+}
+
+/// The number of requests a run is still allowed to make.
+/// Requests are taken from the budget _before_ a task is spawned for them, so no task is ever spawned past the limit.
+/// It's a single atomic, so the closed-loop workers sharing it never wait on a lock.
+pub struct RequestBudget {
+    // `None` means the run is unbounded by count (e.g. only bounded by `--duration`).
+    remaining: Option<AtomicU32>,
+}
+
+impl RequestBudget {
+    pub fn new(total: Option<u32>) -> Self {
+        RequestBudget { remaining: total.map(AtomicU32::new) }
+    }
+
+    /// Takes up to `wanted` requests from the budget in one go. Returns how many were granted.
+    fn acquire(&self, wanted: u32) -> u32 {
+        let (granted, took_last) = self.take(wanted);
+        // Whoever takes the last request announces it, so it's announced exactly once.
+        if took_last {
+            diagnostic("Total call limit reached...");
+        }
+        granted
+    }
+
+    // How many of the `wanted` requests were granted, and whether they used up the budget.
+    fn take(&self, wanted: u32) -> (u32, bool) {
+        let Some(remaining) = &self.remaining else {
+            return (wanted, false);
+        };
+        match remaining.fetch_update(Ordering::AcqRel, Ordering::Acquire, |calls| (calls > 0).then_some(calls.saturating_sub(wanted))) {
+            Ok(calls) => (calls.min(wanted), calls <= wanted),
+            Err(_) => (0, false),
+        }
+    }
+
+    /// Takes one request from the budget. Returns false if the budget is already used up.
+    fn try_acquire(&self) -> bool {
+        self.acquire(1) == 1
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining.as_ref().is_some_and(|calls| calls.load(Ordering::Acquire) == 0)
+    }
+}
+
+/// What happened to a single request: either we got a response back, it failed at the transport level,
+/// or it was never sent because the load generator could not dispatch it in time.
+#[derive(Debug, Clone, Copy)]
+pub enum RequestOutcome {
+    Status(u16),
+    Error(TransportErrorKind),
+    Missed,
+}
+
+/// When a scheduled request counts as missed (or late) because the load generator fell behind.
+#[derive(Debug, Clone, Copy)]
+pub struct MissPolicy {
+    // How long after its intended start a request may still be sent on time.
+    pub max_lag: Duration,
+    // Whether requests later than `max_lag` are dropped (missed), rather than sent late and flagged.
+    pub drop_late: bool,
+    // Open-loop cap on outstanding requests. Requests due while the cap is reached are missed.
+    pub max_in_flight: Option<u64>,
+}
+
+/// The single record sent to the result collector for every request made.
+#[derive(Debug, Clone, Copy)]
+pub struct RequestResult {
+    pub outcome: RequestOutcome,
+    // Index of the rate profile stage the request was scheduled in.
+    pub stage: usize,
+    // Sent, but more than `MissPolicy::max_lag` after its intended start.
+    pub late: bool,
+    pub intended_start: Instant,
+    // Missed requests were never sent.
+    pub send_time: Option<Instant>,
+    // Only requests that got a response back carry timings.
+    pub timing: Option<RequestTiming>,
+}
+
+/// Live counters, updated by every request and read by the result collector while a run is going.
+#[derive(Debug, Default)]
+pub struct RequestCounters {
+    sent: AtomicU64,
+    in_flight: AtomicU64,
+    max_in_flight: AtomicU64,
+}
+
+impl RequestCounters {
+    /// Marks a request as sent and in flight until the returned guard is dropped.
+    /// Returns `None`, without marking anything, if `max_in_flight` requests are in flight already.
+    fn start_request(&self, max_in_flight: Option<u64>) -> Option<InFlightGuard<'_>> {
+        // The check and the increment are a single step, so requests starting at the same time can't all slip under the cap.
+        let in_flight = self.in_flight.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |in_flight| {
+            (!max_in_flight.is_some_and(|max_in_flight| in_flight >= max_in_flight)).then_some(in_flight + 1)
+        }).ok()? + 1;
+        self.sent.fetch_add(1, Ordering::Relaxed);
+        self.max_in_flight.fetch_max(in_flight, Ordering::Relaxed);
+        Some(InFlightGuard { counters: self })
+    }
+
+    pub fn sent(&self) -> u64 {
+        self.sent.load(Ordering::Relaxed)
+    }
+
+    pub fn in_flight(&self) -> u64 {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    pub fn max_in_flight(&self) -> u64 {
+        self.max_in_flight.load(Ordering::Relaxed)
+    }
+}
+
+// Decrementing on drop means requests cancelled by the timeout leave the in-flight count too.
+struct InFlightGuard<'a> {
+    counters: &'a RequestCounters,
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.counters.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Shared state every request needs. Cloning is cheap as everything is reference counted.
+#[derive(Clone)]
+pub struct LoadContext {
+    pub clients: Arc<ClientSet>,
+    pub request_template: Arc<RequestTemplate>,
+    pub budget: Arc<RequestBudget>,
+    pub counters: Arc<RequestCounters>,
+    pub miss_policy: MissPolicy,
+    pub tx_results: Sender<RequestResult>,
+}
+
+impl LoadContext {
+    /// Sends a single request once `intended_start` is reached and reports its result to the collector.
+    async fn make_request(&self, intended_start: Instant, stage: usize) {
+        let request = self.request_template.build_request();
+
+        sleep_until(intended_start).await;
+        let send_time = Instant::now();
+        // Every scheduled request has a deadline of `intended_start + max_lag`. Requests the executor could not get to
+        // by then, or that are due while the in-flight cap is reached, show the load generator itself is the bottleneck.
+        let late = send_time.duration_since(intended_start) > self.miss_policy.max_lag;
+        // A request is in flight from here until its body is fully collected (or it fails or times out).
+        let in_flight = if late && self.miss_policy.drop_late {
+            None
+        } else {
+            self.counters.start_request(self.miss_policy.max_in_flight)
+        };
+        let Some(in_flight) = in_flight else {
+            // Only requests that were actually sent are flagged as late.
+            let _ = self.tx_results.send(RequestResult {
+                outcome: RequestOutcome::Missed,
+                stage,
+                late: false,
+                intended_start,
+                send_time: None,
+                timing: None,
+            }).await;
+            return;
+        };
+
+        // Every request produces exactly one result record, including those that failed.
+        // The timeout covers both the response headers and the full body. Timed out requests are not latency samples.
+        let (client_index, client) = self.clients.pick();
+        let response = timeout(self.request_template.timeout, execute_request(client, request)).await;
+        drop(in_flight);
+        let (outcome, timing) = match response {
+            Err(_) => (RequestOutcome::Error(TransportErrorKind::Timeout), None),
+            Ok(Ok(response)) => {
+                let end_time = Instant::now();
+                // A connection established after the send was waited for, a pooled one that was already open was not.
+                let connected = response.connection_established_at
+                    .map_or(send_time, |established_at| established_at.clamp(send_time, response.headers_received));
+                // Any time spent waiting on the executor after `intended_start` counts towards latency (no co-ordinated omission).
+                let timing = RequestTiming {
+                    latency_micros: end_time.duration_since(intended_start).as_micros() as u64,
+                    service_time_micros: end_time.duration_since(send_time).as_micros() as u64,
+                    phases: PhaseTimings {
+                        queue_wait_micros: send_time.duration_since(intended_start).as_micros() as u64,
+                        connect_micros: connected.duration_since(send_time).as_micros() as u64,
+                        headers_micros: response.headers_received.duration_since(connected).as_micros() as u64,
+                        first_byte_micros: response.first_byte_received.duration_since(response.headers_received).as_micros() as u64,
+                        body_micros: end_time.duration_since(response.first_byte_received).as_micros() as u64,
+                    },
+                    bytes_received: response.bytes_received,
+                    reused_connection: response.connection_established_at.is_some_and(|established_at| established_at < send_time),
+                    version: response.version,
+                    connection: response.connection,
+                    client: client_index,
+                };
+                (RequestOutcome::Status(response.status), Some(timing))
+            }
+            Ok(Err(e)) => (RequestOutcome::Error(e.transport_error_kind()), None),
+        };
+        // The collector may have stopped early (run deadline), in which case the result is dropped.
+        // The request is timed already, so waiting here for room in the channel doesn't affect its measurements.
+        let _ = self.tx_results.send(RequestResult {
+            outcome,
+            stage,
+            late,
+            intended_start,
+            send_time: Some(send_time),
+            timing,
+        }).await;
+    }
+}
+
+/// Open-loop executor: dispatches requests at the rate given by the profile, spread out by the arrival schedule,
+/// until the budget is used up or `stop_at` is reached.
+pub async fn run_open_loop(
+    profile: &RateProfile,
+    arrival_schedule: &mut ArrivalSchedule,
+    context: &LoadContext,
+    run_start: Instant,
+    stop_at: Option<Instant>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut slot_start = run_start;
+    while !context.budget.is_exhausted() {
+        sleep_until(slot_start).await;
+        if stop_at.is_some_and(|stop_at| slot_start >= stop_at) {
+            break;
+        }
+        // The timeline is anchored to `run_start` on the monotonic clock, so a late wake-up never shifts the schedule:
+        // the slot is stretched to cover everything that became due in the meantime, with its original intended start.
+        let slot_end = slot_start.max(Instant::now()) + SCHEDULER_SLOT;
+        let elapsed = slot_start.duration_since(run_start);
+        let intended_starts = arrival_schedule.arrivals_until(slot_end.duration_since(run_start), profile.rate_at(elapsed))
+            .into_iter()
+            .map(|offset| run_start + offset)
+            .collect();
+        sustain_call_rate(intended_starts, profile.stage_at(elapsed), context, stop_at).await?;
+        slot_start = slot_end;
+    }
+    Ok(())
+}
 
 pub async fn sustain_call_rate(
-    rate: u32,
-    address: &Arc<String>,
-    client: Client<HttpConnector, Full<Bytes>>,
-    total_calls: &Arc<Mutex<u32>>,
-    tx: Sender<()>,
-    tx_result_status_codes: UnboundedSender<u16>,
-    tx_result_duration_micros: UnboundedSender<u128>,
-    time_interval: &mut Interval) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    for _ in 0..rate {
-        let calls = Arc::clone(total_calls);
-        let client_conn = client.clone();
-        let addr = Arc::clone(address);
-        let tx_end_check = tx.clone();
-        let tx_status = tx_result_status_codes.clone();
-        let tx_duration = tx_result_duration_micros.clone();
+    intended_starts: Vec<Instant>,
+    stage: usize,
+    context: &LoadContext,
+    stop_at: Option<Instant>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Open-loop scheduling: every request is given its own send time by the arrival schedule.
+    // This is decided up front and does not depend on how quickly earlier requests (or the executor) progress.
+    // Requests intended to start after the end of the run are never scheduled.
+    let due = intended_starts.iter()
+        .take_while(|intended_start| !stop_at.is_some_and(|stop_at| **intended_start >= stop_at))
+        .count();
+    if due == 0 {
+        return Ok(());
+    }
+    // The scheduler takes the whole slot from the `total` budget up front and only spawns what it was granted.
+    let granted = context.budget.acquire(due as u32);
+    for intended_start in intended_starts.into_iter().take(granted as usize) {
+        let task_context = context.clone();
         tokio::spawn(async move {
-            // Make sure we're within `total` limit - strong consistency needed here hence Mutex
-            {
-                let mut job_number = calls.lock().await;
-                if *job_number > 0 {
-                    *job_number -= 1;
-                } else {
-                    if (tx_end_check.send(()).await).is_ok() {
-                        println!("Total call limit reached...");
-                    }
-                    return;
+            task_context.make_request(intended_start, stage).await;
+        });
+    }
+
+    Ok(())
+}
+
+/// Closed-loop executor: `concurrency` workers (virtual users) each send requests back-to-back,
+/// sleeping for `think_time` in between, until the budget is used up or `stop_at` is reached.
+/// Workers still waiting on a request when `run_deadline` passes are aborted, so the report isn't held up by them.
+pub async fn sustain_concurrency(
+    concurrency: u32,
+    think_time: Duration,
+    context: &LoadContext,
+    stop_at: Option<Instant>,
+    run_deadline: Option<Instant>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut workers = Vec::with_capacity(concurrency as usize);
+    for _ in 0..concurrency {
+        let worker_context = context.clone();
+        workers.push(tokio::spawn(async move {
+            while !stop_at.is_some_and(|stop_at| Instant::now() >= stop_at) && worker_context.budget.try_acquire() {
+                // There is no schedule to fall behind on, so a request is intended to start right away.
+                // Closed-loop runs have a single stage.
+                worker_context.make_request(Instant::now(), 0).await;
+                if !think_time.is_zero() {
+                    sleep(think_time).await;
                 }
             }
+        }));
+    }
+    let abort_handles: Vec<AbortHandle> = workers.iter().map(JoinHandle::abort_handle).collect();
+    let join_workers = async move {
+        for worker in workers {
+            worker.await?;
+        }
+        Ok::<(), JoinError>(())
+    };
+    match run_deadline {
+        // Each in-flight request could otherwise take up to `--timeout` past the deadline.
+        Some(run_deadline) => tokio::select! {
+            joined = join_workers => joined?,
+            _ = sleep_until(run_deadline) => abort_handles.iter().for_each(AbortHandle::abort),
+        },
+        None => join_workers.await?,
+    }
+    Ok(())
+}
 
-            let request: Request<Full<Bytes>> = Request::builder()
-                .method(Method::GET)
-                .uri(format!("http://{}", addr))
-                .body(Full::from(" "))
-                .expect("errors constructing request!");
-
-            let start_time = Instant::now();
-            let response_future = client_conn.request(request);
-            let res = response_future.await.unwrap();
-            let (parts, body) = res.into_parts();
-            // Data itself is not as important how long it takes to be fully streamed back to us.
-            // We need all the data to stop timing.
-            let _data = body.collect().await.unwrap();
-            let elapsed_time_millis = start_time.elapsed().as_millis();
-
-            tx_status.send(parts.status.as_u16()).expect("cannot send status_code!");
-            tx_duration.send(elapsed_time_millis).expect("cannot send duration!");
-        });
+async fn execute_request(client: &LoadGenClient, request: Request<Full<Bytes>>) -> Result<ResponseInfo, LoadGenError> {
+    let res = client.request(request).await.map_err(LoadGenError::RequestError)?;
+    let headers_received = Instant::now();
+    let (parts, mut body) = res.into_parts();
+    // The connectors attach the connection's addresses and establishment time to every response.
+    let connection = parts.extensions.get::<HttpInfo>().map(|info| info.local_addr());
+    let connection_established_at = parts.extensions.get::<ConnectionInfo>().map(|info| info.established_at);
+    // Data itself is not as important how long it takes to be fully streamed back to us.
+    // We need all the data to stop timing, and the first frame to time the first byte.
+    let mut first_byte_received = None;
+    let mut bytes_received = 0u64;
+    while let Some(frame) = body.frame().await {
+        let frame = frame.map_err(LoadGenError::BodyError)?;
+        first_byte_received.get_or_insert_with(Instant::now);
+        if let Some(data) = frame.data_ref() {
+            bytes_received += data.len() as u64;
+        }
     }
+    Ok(ResponseInfo {
+        status: parts.status.as_u16(),
+        version: parts.version,
+        connection_established_at,
+        headers_received,
+        // An empty body has no first byte, it is complete as soon as its end is read.
+        first_byte_received: first_byte_received.unwrap_or_else(Instant::now),
+        bytes_received,
+        connection,
+    })
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // Use the pre-determined interval and tick preserve the call rate.
-    time_interval.tick().await;
+    #[test]
+    fn grants_what_is_left_of_the_budget() {
+        let budget = RequestBudget::new(Some(5));
+        assert_eq!(budget.acquire(3), 3);
+        assert_eq!(budget.acquire(3), 2);
+        assert!(budget.is_exhausted());
+        assert_eq!(budget.acquire(3), 0);
+        assert!(!budget.try_acquire());
+    }
 
-    Ok(())
+    #[test]
+    fn grants_everything_without_a_limit() {
+        let budget = RequestBudget::new(None);
+        assert_eq!(budget.acquire(u32::MAX), u32::MAX);
+        assert!(budget.try_acquire());
+        assert!(!budget.is_exhausted());
+    }
+
+    #[test]
+    fn only_the_last_request_reaches_the_limit() {
+        let budget = RequestBudget::new(Some(5));
+        assert_eq!(budget.take(3), (3, false));
+        assert_eq!(budget.take(3), (2, true));
+        assert_eq!(budget.take(3), (0, false));
+    }
+
+    #[test]
+    fn caps_requests_in_flight() {
+        let counters = RequestCounters::default();
+        let first = counters.start_request(Some(2));
+        let second = counters.start_request(Some(2));
+        assert!(first.is_some() && second.is_some());
+        assert!(counters.start_request(Some(2)).is_none());
+        assert_eq!((counters.sent(), counters.in_flight()), (2, 2));
+        drop(first);
+        assert!(counters.start_request(Some(2)).is_some());
+        assert!(counters.start_request(None).is_some());
+        assert_eq!((counters.sent(), counters.max_in_flight()), (4, 2));
+    }
+
+    #[test]
+    fn reaches_the_limit_once_across_workers() {
+        let budget = RequestBudget::new(Some(10_000));
+        let taken: Vec<(u32, u32)> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..8).map(|_| scope.spawn(|| {
+                let (mut granted, mut reached_limit) = (0, 0);
+                loop {
+                    match budget.take(3) {
+                        (0, _) => return (granted, reached_limit),
+                        (calls, took_last) => {
+                            granted += calls;
+                            reached_limit += took_last as u32;
+                        }
+                    }
+                }
+            })).collect();
+            workers.into_iter().map(|worker| worker.join().unwrap()).collect()
+        });
+        assert_eq!(taken.iter().map(|(granted, _)| granted).sum::<u32>(), 10_000);
+        assert_eq!(taken.iter().map(|(_, reached_limit)| reached_limit).sum::<u32>(), 1);
+    }
 }
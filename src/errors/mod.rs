@@ -1,18 +1,223 @@
+use std::error::Error;
 use std::fmt::Display;
+use std::io;
 
 #[derive(Debug)]
 pub enum LoadGenError {
     InvalidPortError(String),
+    InvalidDurationError(String),
+    InvalidRateError(String),
+    InvalidUriError(String),
+    UnsupportedSchemeError(String),
+    MissingHostError(String),
+    InvalidHostError(String),
+    InvalidMethodError(String),
+    InvalidHeaderError(String),
+    BodyFileError(String, io::Error),
+    OutputFileError(String, io::Error),
+    RuntimeError(io::Error),
+    InvalidCpuError(usize),
+    TlsConfigError(String),
+    InvalidProfileError(String),
     NoResultsError,
+    RequestError(hyper_util::client::legacy::Error),
+    BodyError(hyper::Error),
 }
 
-impl std::error::Error for LoadGenError {}
+/// Categories of transport-level failures, reported alongside HTTP status codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TransportErrorKind {
+    Connect,
+    Timeout,
+    Reset,
+    Protocol,
+    BodyRead,
+}
+
+impl LoadGenError {
+    /// Categorises a failed request by walking the chain of errors wrapped by hyper-util, hyper and h2.
+    pub fn transport_error_kind(&self) -> TransportErrorKind {
+        match self {
+            LoadGenError::BodyError(e) => match classify_source_chain(e) {
+                Some(TransportErrorKind::Timeout) => TransportErrorKind::Timeout,
+                _ => TransportErrorKind::BodyRead,
+            },
+            LoadGenError::RequestError(e) => match classify_source_chain(e) {
+                Some(TransportErrorKind::Timeout) => TransportErrorKind::Timeout,
+                _ if e.is_connect() => TransportErrorKind::Connect,
+                Some(kind) => kind,
+                None => TransportErrorKind::Protocol,
+            },
+            _ => TransportErrorKind::Protocol,
+        }
+    }
+}
+
+fn classify_source_chain(error: &(dyn Error + 'static)) -> Option<TransportErrorKind> {
+    let mut current = Some(error);
+    while let Some(e) = current {
+        if let Some(io_error) = e.downcast_ref::<io::Error>() {
+            match io_error.kind() {
+                io::ErrorKind::TimedOut => return Some(TransportErrorKind::Timeout),
+                io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted | io::ErrorKind::BrokenPipe => return Some(TransportErrorKind::Reset),
+                _ => {}
+            }
+        }
+        if let Some(h2_error) = e.downcast_ref::<h2::Error>() {
+            // GOAWAY and RST_STREAM frames both mean the peer tore down our stream.
+            if h2_error.is_go_away() || h2_error.is_reset() {
+                return Some(TransportErrorKind::Reset);
+            }
+            if !h2_error.is_io() {
+                return Some(TransportErrorKind::Protocol);
+            }
+        }
+        if let Some(hyper_error) = e.downcast_ref::<hyper::Error>() {
+            if hyper_error.is_timeout() {
+                return Some(TransportErrorKind::Timeout);
+            }
+            if hyper_error.is_closed() || hyper_error.is_incomplete_message() || hyper_error.is_canceled() {
+                return Some(TransportErrorKind::Reset);
+            }
+        }
+        current = e.source();
+    }
+    None
+}
+
+impl Error for LoadGenError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadGenError::RequestError(e) => Some(e),
+            LoadGenError::BodyError(e) => Some(e),
+            LoadGenError::BodyFileError(_, e) => Some(e),
+            LoadGenError::OutputFileError(_, e) => Some(e),
+            LoadGenError::RuntimeError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Display for TransportErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransportErrorKind::Connect => write!(f, "connect"),
+            TransportErrorKind::Timeout => write!(f, "timeout"),
+            TransportErrorKind::Reset => write!(f, "reset"),
+            TransportErrorKind::Protocol => write!(f, "protocol"),
+            TransportErrorKind::BodyRead => write!(f, "body read"),
+        }
+    }
+}
 
 impl Display for LoadGenError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            LoadGenError::InvalidPortError(port) => write!(f, "[LoadGeneratorError]: {} is an invalid port!", port),
-            LoadGenError::NoResultsError => write!(f, "[LoadGeneratorError]: No results are available! Connection issue for full duration of tests.")
+            LoadGenError::InvalidPortError(port) => write!(f, "[LoadGeneratorError]: {} is an invalid port! Expected a number between 1 and 65535", port),
+            LoadGenError::InvalidDurationError(duration) => write!(f, "[LoadGeneratorError]: {} is an invalid duration! Expected a number with an optional unit (ms, s, m, h), e.g. 30s", duration),
+            LoadGenError::InvalidRateError(rate) => write!(f, "[LoadGeneratorError]: {} is an invalid rate! Expected a non-negative number of requests per second e.g. 0.5 or 100", rate),
+            LoadGenError::InvalidUriError(uri) => write!(f, "[LoadGeneratorError]: {} is an invalid URL! Expected <endpoint>:<port> or a URL such as http://localhost:8080/path", uri),
+            LoadGenError::UnsupportedSchemeError(scheme) => write!(f, "[LoadGeneratorError]: {} is an unsupported scheme! Only http and https are supported", scheme),
+            LoadGenError::MissingHostError(uri) => write!(f, "[LoadGeneratorError]: {} is missing a host!", uri),
+            LoadGenError::InvalidHostError(host) => write!(f, "[LoadGeneratorError]: {} is an invalid host! IPv6 literals need to be bracketed e.g. [::1]:8080", host),
+            LoadGenError::InvalidMethodError(method) => write!(f, "[LoadGeneratorError]: {} is an invalid HTTP method!", method),
+            LoadGenError::InvalidHeaderError(header) => write!(f, "[LoadGeneratorError]: {} is an invalid header! Expected the form 'Name: value'", header),
+            LoadGenError::BodyFileError(path, e) => write!(f, "[LoadGeneratorError]: Cannot read body file {} ({})!", path, e),
+            LoadGenError::OutputFileError(path, e) => write!(f, "[LoadGeneratorError]: Cannot write output to {} ({})!", path, e),
+            LoadGenError::RuntimeError(e) => write!(f, "[LoadGeneratorError]: Cannot build the Tokio runtime ({})!", e),
+            LoadGenError::InvalidCpuError(cpu) => write!(f, "[LoadGeneratorError]: {} is an invalid CPU! Expected the id of one of this machine's cores", cpu),
+            LoadGenError::TlsConfigError(reason) => write!(f, "[LoadGeneratorError]: Invalid TLS configuration, {}!", reason),
+            LoadGenError::InvalidProfileError(profile) => write!(f, "[LoadGeneratorError]: {} is an invalid profile! Expected stages e.g. 10rps:30s,10-100rps:60s, spike:base=10,peak=500,every=60s,length=5s or sine:mean=100,amplitude=50,period=60s", profile),
+            LoadGenError::NoResultsError => write!(f, "[LoadGeneratorError]: No results are available! Connection issue for full duration of tests."),
+            LoadGenError::RequestError(e) => write!(f, "[LoadGeneratorError]: Request failed ({})!", e),
+            LoadGenError::BodyError(e) => write!(f, "[LoadGeneratorError]: Failed reading response body ({})!", e),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use http_body_util::{BodyExt, Empty};
+    use hyper::body::Bytes;
+    use hyper_util::client::legacy::Client;
+    use hyper_util::client::legacy::connect::HttpConnector;
+    use hyper_util::rt::TokioExecutor;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    type TestClient = Client<HttpConnector, Empty<Bytes>>;
+
+    fn client(http2_only: bool) -> TestClient {
+        Client::builder(TokioExecutor::new()).http2_only(http2_only).build_http()
+    }
+
+    // Sends a GET and reads the whole body, categorising whichever of the two failed.
+    async fn error_kind(client: &TestClient, address: SocketAddr) -> TransportErrorKind {
+        let uri = format!("http://{}/", address).parse().unwrap();
+        let error = match client.get(uri).await {
+            Err(e) => LoadGenError::RequestError(e),
+            Ok(response) => match response.into_body().collect().await {
+                Err(e) => LoadGenError::BodyError(e),
+                Ok(_) => panic!("the request succeeded"),
+            },
+        };
+        error.transport_error_kind()
+    }
+
+    // Answers every HTTP/1.1 request with `response` as is, then closes the connection.
+    async fn spawn_raw_server(response: &'static [u8]) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                // A GET without a body fits in a single read.
+                let _ = stream.read(&mut [0u8; 4096]).await;
+                let _ = stream.write_all(response).await;
+            }
+        });
+        address
+    }
+
+    #[tokio::test]
+    async fn categorises_refused_connections() {
+        // Nothing listens on the port once the listener is dropped.
+        let address = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        assert_eq!(error_kind(&client(false), address).await, TransportErrorKind::Connect);
+    }
+
+    #[tokio::test]
+    async fn categorises_connections_closed_before_the_response() {
+        let address = spawn_raw_server(b"").await;
+        assert_eq!(error_kind(&client(false), address).await, TransportErrorKind::Reset);
+    }
+
+    #[tokio::test]
+    async fn categorises_connections_closed_mid_body() {
+        let address = spawn_raw_server(b"HTTP/1.1 200 OK\r\ncontent-length: 100\r\n\r\npartial").await;
+        assert_eq!(error_kind(&client(false), address).await, TransportErrorKind::BodyRead);
+    }
+
+    #[tokio::test]
+    async fn categorises_reset_streams() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let Ok(mut connection) = h2::server::handshake(stream).await else {
+                        return;
+                    };
+                    // Accepting keeps driving the connection, which flushes the RST_STREAM frames.
+                    while let Some(Ok((_request, mut respond))) = connection.accept().await {
+                        respond.send_reset(h2::Reason::INTERNAL_ERROR);
+                    }
+                });
+            }
+        });
+        assert_eq!(error_kind(&client(true), address).await, TransportErrorKind::Reset);
+    }
+}
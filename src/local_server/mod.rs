@@ -0,0 +1,31 @@
+use std::convert::Infallible;
+use std::io;
+
+use http_body_util::Full;
+use hyper::body::{Bytes, Incoming};
+use hyper::service::service_fn;
+use hyper::{Request, Response};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto::Builder;
+use tokio::net::TcpListener;
+
+/// A minimal server answering `200 ok` over HTTP/1.1 and h2c (HTTP/2 with prior knowledge), on every connection accepted.
+/// Used to benchmark the load generator itself, without a real service in the way: by `examples/local_server.rs`,
+/// and in-process by the ignored benchmark test.
+pub async fn serve(listener: TcpListener) -> io::Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(async move {
+            let service = service_fn(|_request: Request<Incoming>| async {
+                Ok::<_, Infallible>(Response::new(Full::new(Bytes::from_static(b"ok"))))
+            });
+            // Serves HTTP/1.1, or HTTP/2 when the connection starts with the h2 preface.
+            let mut builder = Builder::new(TokioExecutor::new());
+            // The load generator opens many streams at once on a single connection.
+            builder.http2().max_concurrent_streams(None);
+            if let Err(e) = builder.serve_connection(TokioIo::new(stream), service).await {
+                println!("Connection error: {}", e);
+            }
+        });
+    }
+}
@@ -1,109 +1,538 @@
 use std::fmt::{Debug};
+use std::num::NonZeroUsize;
 use std::sync::Arc;
 use std::time::Duration;
 
-use clap::Parser;
+use clap::{ArgGroup, Parser};
+use hyper::body::Bytes;
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::Method;
 use hyper_util::client::legacy::Client;
+use hyper_util::client::legacy::connect::HttpConnector;
 use hyper_util::rt::{TokioExecutor, TokioTimer};
-use tokio::sync::{mpsc, Mutex};
-use tokio::time::interval;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
 
-use core::sustain_call_rate;
+use address::parse_address;
+use arrival::{Arrival, ArrivalSchedule};
+use console::{diagnostic, diagnostics_to_stderr};
+use connection::{ConnectionCounters, TimedConnector};
+use core::{run_open_loop, sustain_concurrency, ClientSet, ConnectionStrategy, LoadContext, LoadGenClient, MissPolicy, ProtocolMode, RequestBudget, RequestCounters, RequestResult, RequestTemplate};
 use errors::LoadGenError;
+use parse::{parse_duration, parse_rate};
+use profile::{parse_profile, RateProfile};
+use progress::ProgressReporter;
+use raw_log::RawLog;
+use runtime::{build_runtime, LagProbe, RuntimeOptions};
+use tls::{build_connector, TlsOptions};
 
-use crate::results::process_results;
+use crate::results::{process_results, spawn_collector, SuccessCriteria, RESULT_CHANNEL_CAPACITY};
+use crate::summary::{write_summary, OutputFormat, RunConfig, RunSummary};
 
+mod address;
+mod arrival;
+mod connection;
+mod console;
+#[cfg(test)]
+mod local_server;
 mod results;
 mod errors;
 mod core;
+mod parse;
+mod profile;
+mod progress;
+mod raw_log;
+mod runtime;
+mod summary;
+mod tls;
 
 #[derive(Parser, Debug)]
 #[command(about, long_about = None)]
+#[command(group(ArgGroup::new("protocol").args(["http1", "http2", "auto"])))]
 struct TestParams {
-    /// Fixed call rate (per second)
-    #[arg(short, long, default_value_t = 1)]
-    rate: u32,
+    /// Fixed call rate (per second). Fractional rates are allowed e.g. 0.5 or 2500.5
+    #[arg(short, long, default_value_t = 1.0, value_parser = parse_rate)]
+    rate: f64,
 
-    /// Maximum number of requests
-    #[arg(short, long, default_value_t = 1)]
-    total: u32,
+    /// Rate profile instead of a fixed rate. Stages e.g. 10rps:30s,10-100rps:60s,100rps:30s (a-b ramps linearly),
+    /// spike:base=10,peak=500,every=60s,length=5s or sine:mean=100,amplitude=50,period=60s
+    #[arg(short, long, value_parser = parse_profile, conflicts_with_all = ["rate", "concurrency"])]
+    profile: Option<RateProfile>,
 
-    /// Address of the form <endpoint>:<port>. Example: nghttp2.org:80
+    /// How requests are spread over time: evenly spaced, uniformly random gaps or Poisson arrivals (exponential gaps)
+    #[arg(long, value_enum, default_value_t = Arrival::Constant)]
+    arrival: Arrival,
+
+    /// Seed for the random arrivals, to reproduce a previous run. A random seed is used (and printed) otherwise
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Closed-loop mode: number of workers (virtual users) sending requests back-to-back, instead of a fixed rate
+    #[arg(short, long, conflicts_with = "rate", value_parser = clap::value_parser!(u32).range(1..))]
+    concurrency: Option<u32>,
+
+    /// Time each closed-loop worker waits between receiving a response and sending its next request. Example: 100ms
+    #[arg(long, value_parser = parse_duration, default_value = "0s", requires = "concurrency")]
+    think_time: Duration,
+
+    /// Maximum number of requests. Defaults to 1 unless --duration is given
+    #[arg(short, long)]
+    total: Option<u32>,
+
+    /// How long to keep generating load. Combined with --total, whichever limit is reached first ends the run. Example: 30s, 5m
+    #[arg(short, long, value_parser = parse_duration)]
+    duration: Option<Duration>,
+
+    /// Status codes that count as a success, comma separated. Example: 200,201,204. Defaults to anything outside of 5xx
+    #[arg(long, value_delimiter = ',')]
+    success_status: Vec<u16>,
+
+    /// Treat 4xx responses as failures (ignored when --success-status is given)
+    #[arg(long, default_value_t = false)]
+    fail_on_4xx: bool,
+
+    /// Per-request timeout, covering the response headers and the full body. Example: 500ms, 30s
+    #[arg(long, value_parser = parse_duration, default_value = "30s")]
+    timeout: Duration,
+
+    /// Timeout for establishing a TCP connection. Example: 2s
+    #[arg(long, value_parser = parse_duration)]
+    connect_timeout: Option<Duration>,
+
+    /// Deadline for the whole run. Results collected so far are reported once it passes. Example: 5m
+    #[arg(long, value_parser = parse_duration)]
+    max_duration: Option<Duration>,
+
+    /// How late a request may be sent after its intended start before it's flagged as late. Example: 100ms
+    #[arg(long, value_parser = parse_duration, default_value = "1s")]
+    max_lag: Duration,
+
+    /// Don't send requests later than --max-lag, count them as missed instead
+    #[arg(long, default_value_t = false)]
+    drop_late: bool,
+
+    /// Maximum number of requests in flight. Requests scheduled beyond it are counted as missed
+    #[arg(long, conflicts_with = "concurrency")]
+    max_in_flight: Option<u64>,
+
+    /// HTTP method of every request
+    #[arg(short = 'X', long, value_parser = parse_method, default_value = "GET")]
+    method: Method,
+
+    /// Request header of the form 'Name: value'. Can be repeated
+    #[arg(short = 'H', long = "header", value_parser = parse_header)]
+    headers: Vec<(HeaderName, HeaderValue)>,
+
+    /// Request body
+    #[arg(long, conflicts_with = "body_file")]
+    body: Option<String>,
+
+    /// File whose contents are sent as the request body
+    #[arg(long)]
+    body_file: Option<String>,
+
+    /// PEM file of extra CA certificates to trust for https targets
+    #[arg(long)]
+    cacert: Option<String>,
+
+    /// PEM client certificate (chain) for mutual TLS
+    #[arg(long, requires = "key")]
+    cert: Option<String>,
+
+    /// PEM private key of the client certificate
+    #[arg(long, requires = "cert")]
+    key: Option<String>,
+
+    /// Server name to send via SNI (and verify the certificate against) instead of the URL's host
+    #[arg(long)]
+    sni: Option<String>,
+
+    /// Skip verification of the server's certificate
+    #[arg(short = 'k', long, default_value_t = false)]
+    insecure: bool,
+
+    /// How often to print progress while the run is going. 0s disables progress output. Example: 1s, 10s
+    #[arg(long, value_parser = parse_duration, default_value = "5s")]
+    progress_interval: Duration,
+
+    /// Format of the final report
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+
+    /// File to write the final report to, instead of stdout
+    #[arg(long)]
+    output_file: Option<String>,
+
+    /// File to stream one row per request to while the run is going. CSV for a .csv extension, JSON Lines otherwise.
+    /// Example: requests.jsonl, requests.csv
+    #[arg(long)]
+    raw_log: Option<String>,
+
+    /// Maximum number of open TCP connections. New connections wait until one closes once reached
+    #[arg(long)]
+    max_connections: Option<usize>,
+
+    /// Number of independent clients, each keeping its own HTTP/2 connection, to spread load over several backends
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    connections: u32,
+
+    /// How requests are spread over the --connections
+    #[arg(long, value_enum, default_value_t = ConnectionStrategy::RoundRobin)]
+    connection_strategy: ConnectionStrategy,
+
+    /// Maximum number of idle connections kept in the pool per host
+    #[arg(long)]
+    max_idle_per_host: Option<usize>,
+
+    /// How long idle connections are kept in the pool. Example: 30s
+    #[arg(long, value_parser = parse_duration, default_value = "5s")]
+    pool_idle_timeout: Duration,
+
+    /// HTTP/2 initial stream-level flow control window, in bytes
+    #[arg(long)]
+    h2_stream_window: Option<u32>,
+
+    /// HTTP/2 initial connection-level flow control window, in bytes
+    #[arg(long)]
+    h2_connection_window: Option<u32>,
+
+    /// HTTP/2 streams opened per connection before the server's own limit (SETTINGS_MAX_CONCURRENT_STREAMS) is known
+    #[arg(long)]
+    h2_max_concurrent_streams: Option<usize>,
+
+    /// Let HTTP/2 flow control windows adapt to the bandwidth-delay product (overrides the window sizes)
+    #[arg(long, default_value_t = false)]
+    h2_adaptive_window: bool,
+
+    /// Interval of HTTP/2 keep-alive pings. Disabled by default. Example: 10s
+    #[arg(long, value_parser = parse_duration)]
+    h2_keep_alive_interval: Option<Duration>,
+
+    /// HTTP/2 maximum frame size, in bytes (16384-16777215)
+    #[arg(long, value_parser = clap::value_parser!(u32).range(16_384..=16_777_215))]
+    h2_max_frame_size: Option<u32>,
+
+    /// Number of Tokio worker threads. Defaults to one per core
+    #[arg(long, conflicts_with = "single_thread")]
+    workers: Option<NonZeroUsize>,
+
+    /// Run on a single-threaded (current-thread) Tokio runtime
+    #[arg(long, default_value_t = false)]
+    single_thread: bool,
+
+    /// CPU cores to pin the runtime's threads to, comma separated. Threads are assigned the cores in turn. Example: 0,1,2,3
+    #[arg(long, value_delimiter = ',')]
+    cpu_affinity: Vec<usize>,
+
+    /// Scheduling lag (p99) of the runtime above which the results are flagged as untrustworthy. Example: 5ms
+    #[arg(long, value_parser = parse_duration, default_value = "10ms")]
+    max_scheduling_lag: Duration,
+
+    /// Only speak HTTP/1.1
+    #[arg(long, default_value_t = false)]
+    http1: bool,
+
+    /// Only speak HTTP/2, using prior knowledge (h2c) for http targets. This is the default
+    #[arg(long, default_value_t = false)]
+    http2: bool,
+
+    /// Negotiate HTTP/2 or HTTP/1.1 via ALPN for https targets (http targets use HTTP/1.1)
+    #[arg(long, default_value_t = false)]
+    auto: bool,
+
+    /// Address of the form <endpoint>:<port>, or a full URL with path and query. Example: nghttp2.org:80, http://[::1]:8080/api?id=1
     address: String,
 }
 
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 
     // CLI check
     let args = TestParams::parse();
-    println!("Rate is {} rps", args.rate);
-    println!("Total is {}", args.total);
+    // A JSON report printed to stdout must be the only thing there, for CI to parse it.
+    if args.output == OutputFormat::Json && args.output_file.is_none() {
+        diagnostics_to_stderr();
+    }
+    // The runtime is built explicitly, so its threads can be tuned for the load profile.
+    let runtime_options = RuntimeOptions {
+        workers: args.workers.map(NonZeroUsize::get),
+        single_thread: args.single_thread,
+        cpu_affinity: args.cpu_affinity.clone(),
+    };
+    let runtime = match build_runtime(&runtime_options) {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            diagnostic(&e);
+            return Err(e.into());
+        }
+    };
+    runtime.block_on(run(args))
+}
 
-    // Validation
-    let address = args.address;
-    if let Err(e) = validate_address(address.as_str().trim()) {
-        println!("{}", e);
+async fn run(args: TestParams) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (output, output_file) = (args.output, args.output_file.clone());
+    let summary = generate_load(args).await?;
+    if let Err(e) = write_summary(&summary, output, output_file.as_deref()) {
+        diagnostic(&e);
         return Err(e.into());
     }
-    let address = Arc::new(address);
-    let rate = args.rate;
-
+    // Without a single response there is nothing to measure latency against.
+    if summary.latency.is_none() {
+        return Err(LoadGenError::NoResultsError.into());
+    }
+    Ok(())
+}
 
-    let client = Client::builder(TokioExecutor::new())
-        .pool_idle_timeout(Duration::from_secs(5))
-        .pool_timer(TokioTimer::new())
-        .http2_only(true)
-        .build_http();
+/// Runs the load test described by the CLI arguments, and summarises its results.
+async fn generate_load(args: TestParams) -> Result<RunSummary, Box<dyn std::error::Error + Send + Sync>> {
+    let workers = match (args.single_thread, args.workers) {
+        (true, _) => "current-thread".to_string(),
+        (false, Some(workers)) => format!("{} workers", workers),
+        (false, None) => format!("{} workers", std::thread::available_parallelism().map_or(1, NonZeroUsize::get)),
+    };
+    diagnostic(format_args!("Runtime is {}", workers));
+    let profile = args.profile.clone().unwrap_or(RateProfile::Constant(args.rate));
+    // A stage list has a natural end, which bounds the run just like `--duration`.
+    let duration = [args.duration, profile.duration()].into_iter().flatten().min();
+    // Without any limits, keep the original behaviour of a single call.
+    let total = match (args.total, duration) {
+        (None, None) => Some(1),
+        (total, _) => total,
+    };
+    match args.concurrency {
+        Some(concurrency) => diagnostic(format_args!("Concurrency is {} workers", concurrency)),
+        None => match &profile {
+            RateProfile::Constant(rate) => diagnostic(format_args!("Rate is {} rps", rate)),
+            profile => diagnostic(format_args!("Rate profile is {:?}", profile)),
+        },
+    }
+    let seed = args.seed.unwrap_or_else(rand::random);
+    if args.concurrency.is_none() {
+        diagnostic(format_args!("Arrival is {:?} (seed {})", args.arrival, seed));
+    }
+    if let Some(total) = total {
+        diagnostic(format_args!("Total is {}", total));
+    }
+    if let Some(duration) = duration {
+        diagnostic(format_args!("Duration is {:?}", duration));
+    }
 
-    // The reason for wrapping in Arc and Mutex is to ensure strong consistency when counting down from the max total calls allowed.
-    let total_calls = Arc::new(Mutex::new(args.total));
+    // Validation
+    let target = match parse_address(args.address.trim()) {
+        Ok(target) => target,
+        Err(e) => {
+            diagnostic(&e);
+            return Err(e.into());
+        }
+    };
+    diagnostic(format_args!("Target is {} ({} on port {})", target.uri, target.host, target.port));
+    let body = match (&args.body, &args.body_file) {
+        (Some(body), _) => Bytes::from(body.clone()),
+        (None, Some(path)) => match std::fs::read(path) {
+            Ok(contents) => Bytes::from(contents),
+            Err(e) => {
+                let e = LoadGenError::BodyFileError(path.clone(), e);
+                diagnostic(&e);
+                return Err(e.into());
+            }
+        },
+        (None, None) => Bytes::new(),
+    };
+    let target_uri = target.uri.to_string();
+    let method = args.method.to_string();
+    let request_template = Arc::new(RequestTemplate::new(target.uri, args.method.clone(), args.headers.clone(), body, args.timeout));
+    let stage_labels = match args.concurrency {
+        Some(_) => vec![],
+        None => profile.stage_labels(),
+    };
+    let success_criteria = SuccessCriteria::new(args.success_status.clone(), args.fail_on_4xx);
+    let run_start = Instant::now();
+    // A duration can be parsed fine and still be too long to add to the clock.
+    let deadline = |duration: Duration| run_start.checked_add(duration)
+        .ok_or_else(|| LoadGenError::InvalidDurationError(format!("{:?}", duration)));
+    let (schedule_end, run_deadline) = match (duration.map(deadline).transpose(), args.max_duration.map(deadline).transpose()) {
+        (Ok(schedule_end), Ok(run_deadline)) => (schedule_end, run_deadline),
+        (Err(e), _) | (_, Err(e)) => {
+            diagnostic(&e);
+            return Err(e.into());
+        }
+    };
 
-    // We use a channel and wait for a single message that signals we've reached our call limit.
-    let (tx, rx) = mpsc::channel::<()>(1);
+    // The flags are mutually exclusive, HTTP/2 is used when none are given.
+    let protocol_mode = if args.http2 || !(args.http1 || args.auto) {
+        ProtocolMode::Http2
+    } else if args.http1 {
+        ProtocolMode::Http1
+    } else {
+        ProtocolMode::Auto
+    };
+    diagnostic(format_args!("Protocol mode is {:?}", protocol_mode));
+    let config = RunConfig {
+        target: target_uri,
+        method,
+        protocol: format!("{:?}", protocol_mode).to_lowercase(),
+        stages: stage_labels,
+        connections: args.connections as usize,
+        concurrency: args.concurrency,
+        arrival: args.concurrency.is_none().then(|| format!("{:?}", args.arrival).to_lowercase()),
+        seed: args.concurrency.is_none().then_some(seed),
+        total,
+        duration_secs: duration.map(|duration| duration.as_secs_f64()),
+        timeout_secs: args.timeout.as_secs_f64(),
+        workers,
+        max_scheduling_lag_ms: args.max_scheduling_lag.as_secs_f64() * 1000f64,
+    };
+    let (clients, connection_counters) = match build_clients(&args, protocol_mode) {
+        Ok(clients) => clients,
+        Err(e) => {
+            diagnostic(&e);
+            return Err(e.into());
+        }
+    };
 
-    // Another two channels will be used solely for capturing raw results (status_code and duration).
-    // Their results will be collected into their respective vectors.
-    let (tx_result_status_codes, mut rx_status_codes) = mpsc::unbounded_channel::<u16>();
-    let (tx_result_duration_micros, mut rx_durations) = mpsc::unbounded_channel::<u128>();
-    let mut result_errors: Vec<u16> = vec![];
-    let mut result_durations: Vec<u128> = vec![];
+    // Every request sends a single result record to a background collector, which aggregates them as they arrive.
+    // Only the aggregates (counts and fixed-size histograms) are kept, not the individual records.
+    // The channel is bounded, so a collector that falls behind holds up requests rather than queuing results without limit.
+    let (tx_results, rx_results) = mpsc::channel::<RequestResult>(RESULT_CHANNEL_CAPACITY);
+    let counters = Arc::new(RequestCounters::default());
+    let progress = (!args.progress_interval.is_zero()).then(|| ProgressReporter::new(args.progress_interval, Arc::clone(&counters)));
+    let raw_log = match args.raw_log.as_deref().map(|path| RawLog::create(path, run_start)).transpose() {
+        Ok(raw_log) => raw_log,
+        Err(e) => {
+            diagnostic(&e);
+            return Err(e.into());
+        }
+    };
+    let collector = spawn_collector(rx_results, run_deadline, Arc::clone(&counters), connection_counters, progress, raw_log);
+    // Samples the runtime's scheduling lag for the whole run, as a self-check of the load generator.
+    let lag_probe = LagProbe::spawn();
 
-    // We need to sustain the call rate, therefore we use tokio's interval.
-    let mut time_interval = interval(Duration::from_secs(1));
-    time_interval.tick().await; // the first tick is immediate.
+    let context = LoadContext {
+        clients: Arc::new(ClientSet::new(clients, args.connection_strategy)),
+        request_template,
+        // The budget counts down from the max total calls allowed. The executors take from it before spawning any work.
+        budget: Arc::new(RequestBudget::new(total)),
+        counters,
+        tx_results,
+        // Requests the generator can't dispatch in time are flagged as late, or counted as missed and not sent at all.
+        miss_policy: MissPolicy {
+            max_lag: args.max_lag,
+            drop_late: args.drop_late,
+            max_in_flight: args.max_in_flight,
+        },
+    };
 
-    while rx.is_empty() {
-        sustain_call_rate(rate, &address, client.clone(), &total_calls, tx.clone(), tx_result_status_codes.clone(), tx_result_duration_micros.clone(), &mut time_interval).await.unwrap();
+    // Both executors stop at whichever of `--duration` and the run deadline comes first.
+    let stop_at = [schedule_end, run_deadline].into_iter().flatten().min();
+    if let Some(concurrency) = args.concurrency {
+        sustain_concurrency(concurrency, args.think_time, &context, stop_at, run_deadline).await?;
+    } else {
+        // We need to sustain the call rate (given by the profile at each point of the run), spread out by the arrival schedule.
+        // Scheduling stops once the budget is used up, the `--duration` has elapsed or the run deadline has passed.
+        let mut arrival_schedule = ArrivalSchedule::new(args.arrival, seed);
+        run_open_loop(&profile, &mut arrival_schedule, &context, run_start, stop_at).await?;
     }
+    // Dropping our own context (and its sender) means the collector finishes once every spawned task has finished.
+    drop(context);
+
     // Result processing
-    // This can be optimized further, we're doing full buffering of all the response codes and durations
-    // in their respective channels until _after_ the total calls have been made. Our load generator
-    // also has a flaw in that it will hang indefinitely if no requests can be made. This can be addressed by wrapping
-    // the result collection in a tokio timeout itself.
-    // We use try_recv to know when an Empty error occurs as a signal for no more results (even if they are delayed).
-    while  result_errors.len() != args.total as usize {
-        result_errors.push(rx_status_codes.recv().await.unwrap());
-    }
+    // Every request is bounded by the per-request timeout, and the collector is bounded by the run deadline (if any).
+    let mut results = collector.await?;
+    results.set_scheduling_lag(lag_probe.finish().await?);
+    Ok(process_results(results, &success_criteria, config).await?)
+}
 
-    while result_durations.len() != args.total as usize {
-        result_durations.push(rx_durations.recv().await.unwrap());
-    }
+/// Builds the `--connections` independent clients with the TLS, pool and HTTP/2 settings of the CLI,
+/// and the counters of every connection they open.
+fn build_clients(args: &TestParams, protocol_mode: ProtocolMode) -> Result<(Vec<LoadGenClient>, Arc<ConnectionCounters>), LoadGenError> {
+    let mut http_connector = HttpConnector::new();
+    http_connector.set_connect_timeout(args.connect_timeout);
+    let tls_options = TlsOptions {
+        ca_file: args.cacert.clone(),
+        client_cert_file: args.cert.clone(),
+        client_key_file: args.key.clone(),
+        server_name: args.sni.clone(),
+        insecure: args.insecure,
+    };
+    let connector = build_connector(http_connector, &tls_options, protocol_mode)?;
 
-    if process_results(result_durations, result_errors).await.is_err() {
-        return Err(LoadGenError::NoResultsError.into());
+    // Without `http2_only`, the client speaks HTTP/1.1 unless the TLS handshake negotiated h2.
+    let mut client_builder = Client::builder(TokioExecutor::new());
+    client_builder
+        .pool_idle_timeout(args.pool_idle_timeout)
+        .pool_max_idle_per_host(args.max_idle_per_host.unwrap_or(usize::MAX))
+        .pool_timer(TokioTimer::new())
+        // Needed for HTTP/2 keep-alive pings.
+        .timer(TokioTimer::new())
+        .http2_only(protocol_mode == ProtocolMode::Http2)
+        .http2_initial_stream_window_size(args.h2_stream_window)
+        .http2_initial_connection_window_size(args.h2_connection_window)
+        .http2_max_frame_size(args.h2_max_frame_size)
+        .http2_keep_alive_interval(args.h2_keep_alive_interval);
+    if let Some(max_concurrent_streams) = args.h2_max_concurrent_streams {
+        client_builder.http2_initial_max_send_streams(max_concurrent_streams);
     }
-    Ok(())
+    // Adaptive windows replace any fixed window sizes, so only turn them on when asked to.
+    if args.h2_adaptive_window {
+        client_builder.http2_adaptive_window(true);
+    }
+    // Times and counts connections, for the connect phase of requests and the connection report.
+    let connection_counters = Arc::new(ConnectionCounters::default());
+    let connector = TimedConnector::new(connector, Arc::clone(&connection_counters), args.max_connections);
+    // Every client has its own pool, so each keeps its own HTTP/2 connection.
+    let clients = (0..args.connections).map(|_| client_builder.build(connector.clone())).collect();
+    Ok((clients, connection_counters))
 }
 
+fn parse_method(method: &str) -> Result<Method, LoadGenError> {
+    Method::from_bytes(method.to_uppercase().as_bytes()).map_err(|_| LoadGenError::InvalidMethodError(method.to_string()))
+}
+
+fn parse_header(header: &str) -> Result<(HeaderName, HeaderValue), LoadGenError> {
+    let invalid_header = || LoadGenError::InvalidHeaderError(header.to_string());
+    let (name, value) = header.split_once(':').ok_or_else(invalid_header)?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes()).map_err(|_| invalid_header())?;
+    let value = HeaderValue::from_str(value.trim()).map_err(|_| invalid_header())?;
+    Ok((name, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
 
-fn validate_address(address: &str) -> Result<(), LoadGenError> {
-    let parts: Vec<&str> = address.split(':').collect();
-    let port = parts[1];
-    if port.parse::<u32>().is_err() {
-        println!("Specified port is invalid!");
-        return Err(LoadGenError::InvalidPortError(port.to_string()));
+    // The same run as `--rate 50000 --duration 5s --connections 4` against the local benchmarking server, in-process,
+    // checking the load generator keeps up with its own schedule. It needs a release build and spare cores,
+    // so it only runs when asked to: cargo test --release -- --ignored --nocapture
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore = "benchmark, run with --release"]
+    async fn sustains_50k_rps() {
+        const RATE: f64 = 50_000f64;
+        const DURATION_SECS: f64 = 5f64;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(local_server::serve(listener));
+
+        let args = TestParams::parse_from([
+            "toy-loadgen",
+            "--rate", "50000",
+            "--duration", "5s",
+            "--connections", "4",
+            "--progress-interval", "0s",
+            target.as_str(),
+        ]);
+        let summary = generate_load(args).await.unwrap();
+        write_summary(&summary, OutputFormat::Text, None).unwrap();
+
+        // Give or take one arrival to rounding at the end of the run.
+        assert!(summary.counts.scheduled.abs_diff((RATE * DURATION_SECS) as u64) <= 1, "{:?}", summary.counts);
+        assert_eq!(summary.counts.missed, 0);
+        assert_eq!(summary.counts.late, 0);
+        assert!(summary.errors.is_empty(), "{:?}", summary.errors);
+        assert_eq!(summary.status_codes.keys().collect::<Vec<_>>(), vec![&200]);
+        // Sends that fell behind the schedule stretch the achieved rate, even when none of them were late enough to be flagged.
+        assert!((summary.intended_rps - RATE).abs() < 0.01 * RATE, "intended {:.0} rps", summary.intended_rps);
+        assert!(summary.achieved_rps >= 0.95 * RATE, "achieved {:.0} rps", summary.achieved_rps);
     }
-    Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,64 @@
+use std::time::Duration;
+
+use crate::errors::LoadGenError;
+
+/// A whole number with an optional unit (ms, s, m, h) e.g. `500ms`, `30s` or `5m`. Without a unit, seconds are assumed.
+pub fn parse_duration(duration: &str) -> Result<Duration, LoadGenError> {
+    let duration = duration.trim();
+    let split_at = duration.find(|c: char| !c.is_ascii_digit()).unwrap_or(duration.len());
+    let (value, unit) = duration.split_at(split_at);
+    let invalid_duration = || LoadGenError::InvalidDurationError(duration.to_string());
+    let value = value.parse::<u64>().map_err(|_| invalid_duration())?;
+    match unit {
+        "ms" => Ok(Duration::from_millis(value)),
+        "" | "s" => Ok(Duration::from_secs(value)),
+        "m" => value.checked_mul(60).map(Duration::from_secs).ok_or_else(invalid_duration),
+        "h" => value.checked_mul(60 * 60).map(Duration::from_secs).ok_or_else(invalid_duration),
+        _ => Err(invalid_duration()),
+    }
+}
+
+/// A non-negative, possibly fractional rate (per second) with an optional `rps` suffix e.g. `100rps`, `0.5rps` or `100`.
+pub fn parse_rate(rate: &str) -> Result<f64, LoadGenError> {
+    let trimmed = rate.trim();
+    match trimmed.strip_suffix("rps").unwrap_or(trimmed).parse::<f64>() {
+        Ok(value) if value.is_finite() && value >= 0f64 => Ok(value),
+        _ => Err(LoadGenError::InvalidRateError(rate.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations_with_units() {
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("30").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("5m").unwrap(), Duration::from_secs(300));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7200));
+    }
+
+    #[test]
+    fn rejects_invalid_durations() {
+        for duration in ["", "s", "1.5s", "-1s", "10d", "18446744073709551615m", "5124095576030432h"] {
+            assert!(matches!(parse_duration(duration), Err(LoadGenError::InvalidDurationError(_))), "{}", duration);
+        }
+    }
+
+    #[test]
+    fn parses_fractional_rates() {
+        assert_eq!(parse_rate("100").unwrap(), 100f64);
+        assert_eq!(parse_rate("0.5rps").unwrap(), 0.5);
+        assert_eq!(parse_rate("2500.5").unwrap(), 2500.5);
+        assert_eq!(parse_rate("0").unwrap(), 0f64);
+    }
+
+    #[test]
+    fn rejects_invalid_rates() {
+        for rate in ["", "rps", "-1", "NaN", "inf", "fast"] {
+            assert!(matches!(parse_rate(rate), Err(LoadGenError::InvalidRateError(_))), "{}", rate);
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use std::f64::consts::PI;
+use std::time::Duration;
+
+use crate::errors::LoadGenError;
+use crate::parse::{parse_duration, parse_rate};
+
+/// How the target request rate changes over the course of a run.
+/// Results are bucketed by stage, so latency can be compared across the different parts of a profile.
+#[derive(Debug, Clone)]
+pub enum RateProfile {
+    /// The same rate for the whole run (`--rate`).
+    Constant(f64),
+    /// A list of stages run one after the other, each either holding a rate or ramping linearly between two rates.
+    Stages(Vec<Stage>),
+    /// `base` rate, with a burst of `peak` rate lasting `length` at the start of every `every`.
+    Spike { base: f64, peak: f64, every: Duration, length: Duration },
+    /// A rate oscillating around `mean` by +/- `amplitude` with the given `period`.
+    Sine { mean: f64, amplitude: f64, period: Duration },
+}
+
+#[derive(Debug, Clone)]
+pub struct Stage {
+    from: f64,
+    to: f64,
+    duration: Duration,
+}
+
+impl RateProfile {
+    /// The target rate (per second, possibly fractional) at `elapsed` into the run.
+    pub fn rate_at(&self, elapsed: Duration) -> f64 {
+        match self {
+            RateProfile::Constant(rate) => *rate,
+            RateProfile::Stages(stages) => {
+                let mut stage_start = Duration::ZERO;
+                for stage in stages {
+                    if elapsed < stage_start + stage.duration {
+                        let progress = (elapsed - stage_start).as_secs_f64() / stage.duration.as_secs_f64();
+                        return stage.from + (stage.to - stage.from) * progress;
+                    }
+                    stage_start += stage.duration;
+                }
+                0f64
+            }
+            RateProfile::Spike { base, peak, every, length } => {
+                if in_spike(elapsed, *every, *length) { *peak } else { *base }
+            }
+            RateProfile::Sine { mean, amplitude, period } => {
+                let phase = 2f64 * PI * elapsed.as_secs_f64() / period.as_secs_f64();
+                (mean + amplitude * phase.sin()).max(0f64)
+            }
+        }
+    }
+
+    /// The index (into `stage_labels`) of the stage at `elapsed` into the run.
+    pub fn stage_at(&self, elapsed: Duration) -> usize {
+        match self {
+            RateProfile::Constant(_) => 0,
+            RateProfile::Stages(stages) => {
+                let mut stage_start = Duration::ZERO;
+                for (index, stage) in stages.iter().enumerate() {
+                    stage_start += stage.duration;
+                    if elapsed < stage_start {
+                        return index;
+                    }
+                }
+                stages.len() - 1
+            }
+            RateProfile::Spike { every, length, .. } => in_spike(elapsed, *every, *length) as usize,
+            RateProfile::Sine { period, .. } => {
+                // The first half of every period is above the mean, the second half below it.
+                let into_period = elapsed.as_secs_f64() % period.as_secs_f64();
+                (into_period >= period.as_secs_f64() / 2f64) as usize
+            }
+        }
+    }
+
+    pub fn stage_labels(&self) -> Vec<String> {
+        match self {
+            RateProfile::Constant(rate) => vec![format!("constant {}rps", rate)],
+            RateProfile::Stages(stages) => stages.iter().enumerate().map(|(index, stage)| {
+                if stage.from == stage.to {
+                    format!("stage {}: {}rps for {:?}", index + 1, stage.from, stage.duration)
+                } else {
+                    format!("stage {}: {}-{}rps over {:?}", index + 1, stage.from, stage.to, stage.duration)
+                }
+            }).collect(),
+            RateProfile::Spike { base, peak, .. } => vec![format!("base {}rps", base), format!("spike {}rps", peak)],
+            RateProfile::Sine { mean, .. } => vec![format!("above mean {}rps", mean), format!("below mean {}rps", mean)],
+        }
+    }
+
+    /// How long the profile runs for, if it has a natural end.
+    pub fn duration(&self) -> Option<Duration> {
+        match self {
+            RateProfile::Stages(stages) => Some(stages.iter().map(|stage| stage.duration).sum()),
+            _ => None,
+        }
+    }
+}
+
+fn in_spike(elapsed: Duration, every: Duration, length: Duration) -> bool {
+    elapsed.as_secs_f64() % every.as_secs_f64() < length.as_secs_f64()
+}
+
+/// Parses a profile given on the CLI. Supported forms:
+/// * stages: `10rps:30s,100rps:60s,10rps:30s`, where a stage of `10-100rps:60s` ramps linearly from 10 to 100 rps
+/// * spike: `spike:base=10,peak=500,every=60s,length=5s`
+/// * sine: `sine:mean=100,amplitude=50,period=60s`
+pub fn parse_profile(profile: &str) -> Result<RateProfile, LoadGenError> {
+    let invalid_profile = || LoadGenError::InvalidProfileError(profile.to_string());
+    let profile = profile.trim();
+    // Any malformed part is reported against the whole profile, rather than as an invalid rate or duration.
+    let rate = |rate: &str| parse_rate(rate).map_err(|_| invalid_profile());
+    let duration = |duration: &str| parse_duration(duration).map_err(|_| invalid_profile());
+    if let Some(params) = profile.strip_prefix("spike:") {
+        let params = parse_params(params).ok_or_else(invalid_profile)?;
+        let param = |name: &str| param(&params, name).ok_or_else(invalid_profile);
+        let every = duration(param("every")?)?;
+        if every.is_zero() {
+            return Err(invalid_profile());
+        }
+        return Ok(RateProfile::Spike {
+            base: rate(param("base")?)?,
+            peak: rate(param("peak")?)?,
+            every,
+            length: duration(param("length")?)?,
+        });
+    }
+    if let Some(params) = profile.strip_prefix("sine:") {
+        let params = parse_params(params).ok_or_else(invalid_profile)?;
+        let param = |name: &str| param(&params, name).ok_or_else(invalid_profile);
+        let period = duration(param("period")?)?;
+        if period.is_zero() {
+            return Err(invalid_profile());
+        }
+        return Ok(RateProfile::Sine {
+            mean: rate(param("mean")?)?,
+            amplitude: rate(param("amplitude")?)?,
+            period,
+        });
+    }
+
+    let mut stages = vec![];
+    // The stages add up to the duration of the run, which has to fit in a `Duration`.
+    let mut total_duration = Duration::ZERO;
+    for stage in profile.split(',') {
+        let (rates, stage_duration) = stage.trim().split_once(':').ok_or_else(invalid_profile)?;
+        let (from, to) = rates.split_once('-').unwrap_or((rates, rates));
+        let stage_duration = duration(stage_duration)?;
+        if stage_duration.is_zero() {
+            return Err(invalid_profile());
+        }
+        total_duration = total_duration.checked_add(stage_duration).ok_or_else(invalid_profile)?;
+        stages.push(Stage {
+            from: rate(from)?,
+            to: rate(to)?,
+            duration: stage_duration,
+        });
+    }
+    Ok(RateProfile::Stages(stages))
+}
+
+fn parse_params(params: &str) -> Option<Vec<(&str, &str)>> {
+    params.split(',').map(|param| param.trim().split_once('=')).collect()
+}
+
+fn param<'a>(params: &[(&str, &'a str)], name: &str) -> Option<&'a str> {
+    params.iter().find(|(key, _)| *key == name).map(|(_, value)| *value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_rate(profile: &RateProfile, elapsed: Duration, expected: f64) {
+        let rate = profile.rate_at(elapsed);
+        assert!((rate - expected).abs() < 1e-9, "rate at {:?} is {}, expected {}", elapsed, rate, expected);
+    }
+
+    #[test]
+    fn parses_stages() {
+        let profile = parse_profile("10rps:30s,10-100rps:60s,100:30s").unwrap();
+        assert_eq!(profile.duration(), Some(Duration::from_secs(120)));
+        assert_eq!(profile.stage_labels(), vec![
+            "stage 1: 10rps for 30s",
+            "stage 2: 10-100rps over 60s",
+            "stage 3: 100rps for 30s",
+        ]);
+        assert_rate(&profile, Duration::from_secs(0), 10f64);
+        assert_rate(&profile, Duration::from_secs(60), 55f64);
+        assert_rate(&profile, Duration::from_secs(100), 100f64);
+        // Nothing is sent past the last stage.
+        assert_rate(&profile, Duration::from_secs(120), 0f64);
+    }
+
+    #[test]
+    fn ramps_up_from_zero() {
+        let profile = parse_profile("0-100rps:60s").unwrap();
+        assert_rate(&profile, Duration::ZERO, 0f64);
+        assert_rate(&profile, Duration::from_millis(1), 100f64 / 60_000f64);
+        assert_rate(&profile, Duration::from_secs(30), 50f64);
+        assert_rate(&profile, Duration::from_secs(59), 100f64 * 59f64 / 60f64);
+    }
+
+    #[test]
+    fn finds_the_stage_at_each_point_of_the_run() {
+        let profile = parse_profile("10rps:30s,100rps:60s").unwrap();
+        assert_eq!(profile.stage_at(Duration::ZERO), 0);
+        assert_eq!(profile.stage_at(Duration::from_millis(29_999)), 0);
+        assert_eq!(profile.stage_at(Duration::from_secs(30)), 1);
+        // Past the end, results still belong to the last stage.
+        assert_eq!(profile.stage_at(Duration::from_secs(200)), 1);
+    }
+
+    #[test]
+    fn parses_spikes() {
+        let profile = parse_profile("spike:base=10,peak=500,every=60s,length=5s").unwrap();
+        assert_eq!(profile.duration(), None);
+        assert_rate(&profile, Duration::ZERO, 500f64);
+        assert_rate(&profile, Duration::from_secs(5), 10f64);
+        assert_rate(&profile, Duration::from_secs(61), 500f64);
+        assert_eq!(profile.stage_at(Duration::from_secs(1)), 1);
+        assert_eq!(profile.stage_at(Duration::from_secs(30)), 0);
+    }
+
+    #[test]
+    fn parses_sines() {
+        let profile = parse_profile("sine:mean=100,amplitude=50,period=60s").unwrap();
+        assert_rate(&profile, Duration::ZERO, 100f64);
+        assert_rate(&profile, Duration::from_secs(15), 150f64);
+        assert_rate(&profile, Duration::from_secs(45), 50f64);
+        assert_eq!(profile.stage_at(Duration::from_secs(15)), 0);
+        assert_eq!(profile.stage_at(Duration::from_secs(45)), 1);
+    }
+
+    #[test]
+    fn clamps_sine_troughs_at_zero() {
+        let profile = parse_profile("sine:mean=10,amplitude=50,period=60s").unwrap();
+        assert_rate(&profile, Duration::from_secs(45), 0f64);
+    }
+
+    #[test]
+    fn rejects_invalid_profiles() {
+        for profile in [
+            "",
+            "10rps",
+            "10rps:0s",
+            "10rps:abc",
+            "fast:30s",
+            "-10rps:30s",
+            "spike:base=10,peak=500,every=0s,length=5s",
+            "spike:base=10,peak=500,length=5s",
+            "sine:mean=100,amplitude=50",
+            "sine:mean=100,amplitude=50,period=1.5s",
+            "10rps:18446744073709551615s,10rps:1s",
+        ] {
+            assert!(matches!(parse_profile(profile), Err(LoadGenError::InvalidProfileError(_))), "{}", profile);
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use hdrhistogram::Histogram;
+use tokio::time::{interval_at, Instant, Interval};
+
+use crate::console::diagnostic;
+use crate::core::{RequestCounters, RequestOutcome, RequestResult};
+
+/// Prints a line of interval statistics every `period` while a run is going, so long runs don't look frozen.
+/// It is driven by the result collector: every result is recorded here as well as in the final aggregates.
+pub struct ProgressReporter {
+    ticker: Interval,
+    counters: Arc<RequestCounters>,
+    run_start: Instant,
+    interval_start: Instant,
+    // Interval statistics are reset after every report.
+    interval_latency: Histogram<u64>,
+    interval_completed: u64,
+    interval_errors: u64,
+    completed: u64,
+    errors: u64,
+    missed: u64,
+}
+
+impl ProgressReporter {
+    pub fn new(period: Duration, counters: Arc<RequestCounters>) -> Self {
+        let now = Instant::now();
+        ProgressReporter {
+            // The first report is one period in, not immediately.
+            ticker: interval_at(now + period, period),
+            counters,
+            run_start: now,
+            interval_start: now,
+            interval_latency: Histogram::new(3).expect("invalid histogram bounds!"),
+            interval_completed: 0,
+            interval_errors: 0,
+            completed: 0,
+            errors: 0,
+            missed: 0,
+        }
+    }
+
+    pub fn record(&mut self, result: &RequestResult) {
+        match result.outcome {
+            // Missed requests were never sent, so they never complete either.
+            RequestOutcome::Missed => {
+                self.missed += 1;
+                return;
+            }
+            RequestOutcome::Error(_) => {
+                self.errors += 1;
+                self.interval_errors += 1;
+            }
+            RequestOutcome::Status(_) => {}
+        }
+        self.completed += 1;
+        self.interval_completed += 1;
+        if let Some(timing) = result.timing {
+            self.interval_latency.saturating_record(timing.latency_micros);
+        }
+    }
+
+    pub async fn tick(&mut self) {
+        self.ticker.tick().await;
+    }
+
+    pub fn report(&mut self) {
+        let now = Instant::now();
+        let sent = self.counters.sent();
+        let interval_secs = now.duration_since(self.interval_start).as_secs_f64();
+        let achieved_rps = self.interval_completed as f64 / interval_secs.max(f64::EPSILON);
+        diagnostic(format_args!(
+            "[{:>4}s] sent: {}, completed: {}, in flight: {}, rps: {:.1}, p50: {:.3}ms, p99: {:.3}ms, errors: {} ({} total), missed: {}",
+            now.duration_since(self.run_start).as_secs(),
+            sent,
+            self.completed,
+            // The same gauge as the final report, so results still queued for the collector aren't counted as in flight.
+            self.counters.in_flight(),
+            achieved_rps,
+            self.interval_latency.value_at_percentile(50.0) as f64 / 1000f64,
+            self.interval_latency.value_at_percentile(99.0) as f64 / 1000f64,
+            self.interval_errors,
+            self.errors,
+            self.missed,
+        ));
+        self.interval_start = now;
+        self.interval_latency.reset();
+        self.interval_completed = 0;
+        self.interval_errors = 0;
+    }
+}
\ No newline at end of file
@@ -0,0 +1,159 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use serde::Serialize;
+use tokio::sync::mpsc::{self, Sender};
+use tokio::task::{spawn_blocking, JoinHandle};
+use tokio::time::Instant;
+
+use crate::core::{RequestOutcome, RequestResult};
+use crate::errors::LoadGenError;
+
+const CSV_HEADER: &str = "scheduled_us,sent_us,ttfb_us,latency_us,status,error,late,bytes_received,connection";
+// Rows that can be queued up for the writer. Once full, the collector waits for the disk to catch up.
+const ROW_CHANNEL_CAPACITY: usize = 64 * 1024;
+
+/// Format of the raw log, picked from the file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RawLogFormat {
+    JsonLines,
+    Csv,
+}
+
+/// Streams one row per request to a file while the run is going, for offline analysis.
+/// The (blocking) file writes happen on a dedicated thread, so they never stall the collector on the runtime.
+pub struct RawLog {
+    path: String,
+    tx_rows: Sender<RequestResult>,
+    writer: JoinHandle<Result<(), LoadGenError>>,
+}
+
+impl RawLog {
+    /// Creates (or truncates) the log file and starts its writer thread. A `.csv` extension writes CSV, anything else JSON Lines.
+    pub fn create(path: &str, run_start: Instant) -> Result<Self, LoadGenError> {
+        let mut writer = RawLogWriter::create(path, run_start)?;
+        let (tx_rows, mut rx_rows) = mpsc::channel::<RequestResult>(ROW_CHANNEL_CAPACITY);
+        let writer = spawn_blocking(move || {
+            // Stops at the first error, which is returned once the log is finished.
+            while let Some(result) = rx_rows.blocking_recv() {
+                writer.write(&result)?;
+            }
+            writer.flush()
+        });
+        Ok(RawLog { path: path.to_string(), tx_rows, writer })
+    }
+
+    /// Queues a row for the writer. Returns false once the writer has stopped, after a write error.
+    pub async fn write(&self, result: RequestResult) -> bool {
+        self.tx_rows.send(result).await.is_ok()
+    }
+
+    /// Waits for every queued row to be written and flushed to the file.
+    pub async fn finish(self) -> Result<(), LoadGenError> {
+        drop(self.tx_rows);
+        match self.writer.await {
+            Ok(written) => written,
+            Err(e) => Err(LoadGenError::OutputFileError(self.path, io::Error::other(e))),
+        }
+    }
+}
+
+/// Writes the rows of the raw log, on the writer thread.
+struct RawLogWriter {
+    path: String,
+    format: RawLogFormat,
+    writer: BufWriter<File>,
+    // Times in the log are offsets (in microseconds) from the start of the run.
+    run_start: Instant,
+}
+
+/// A single row of the raw log. Fields a request doesn't have (e.g. timings of a failed request) are left empty.
+#[derive(Debug, Serialize)]
+struct RawRecord {
+    scheduled_us: u64,
+    sent_us: Option<u64>,
+    ttfb_us: Option<u64>,
+    latency_us: Option<u64>,
+    status: Option<u16>,
+    error: Option<String>,
+    late: bool,
+    bytes_received: Option<u64>,
+    connection: Option<String>,
+}
+
+impl RawLogWriter {
+    fn create(path: &str, run_start: Instant) -> Result<Self, LoadGenError> {
+        let format = if path.to_lowercase().ends_with(".csv") {
+            RawLogFormat::Csv
+        } else {
+            RawLogFormat::JsonLines
+        };
+        let file = File::create(path).map_err(|e| LoadGenError::OutputFileError(path.to_string(), e))?;
+        let mut raw_log = RawLogWriter {
+            path: path.to_string(),
+            format,
+            writer: BufWriter::new(file),
+            run_start,
+        };
+        if format == RawLogFormat::Csv {
+            writeln!(raw_log.writer, "{}", CSV_HEADER).map_err(|e| raw_log.error(e))?;
+        }
+        Ok(raw_log)
+    }
+
+    fn write(&mut self, result: &RequestResult) -> Result<(), LoadGenError> {
+        let record = self.to_record(result);
+        let written = match self.format {
+            RawLogFormat::JsonLines => serde_json::to_writer(&mut self.writer, &record)
+                .map_err(io::Error::from)
+                .and_then(|_| writeln!(self.writer)),
+            RawLogFormat::Csv => writeln!(self.writer, "{},{},{},{},{},{},{},{},{}",
+                                          record.scheduled_us,
+                                          csv_field(record.sent_us),
+                                          csv_field(record.ttfb_us),
+                                          csv_field(record.latency_us),
+                                          csv_field(record.status),
+                                          csv_field(record.error),
+                                          record.late,
+                                          csv_field(record.bytes_received),
+                                          csv_field(record.connection)),
+        };
+        written.map_err(|e| self.error(e))
+    }
+
+    fn flush(&mut self) -> Result<(), LoadGenError> {
+        self.writer.flush().map_err(|e| self.error(e))
+    }
+
+    fn to_record(&self, result: &RequestResult) -> RawRecord {
+        let (status, error) = match result.outcome {
+            RequestOutcome::Status(status) => (Some(status), None),
+            RequestOutcome::Error(kind) => (None, Some(kind.to_string())),
+            RequestOutcome::Missed => (None, Some("missed".to_string())),
+        };
+        RawRecord {
+            scheduled_us: self.offset_micros(result.intended_start),
+            sent_us: result.send_time.map(|send_time| self.offset_micros(send_time)),
+            ttfb_us: result.timing.map(|timing| timing.phases.time_to_first_byte_micros()),
+            latency_us: result.timing.map(|timing| timing.latency_micros),
+            status,
+            error,
+            late: result.late,
+            bytes_received: result.timing.map(|timing| timing.bytes_received),
+            connection: result.timing.and_then(|timing| timing.connection).map(|connection| connection.to_string()),
+        }
+    }
+
+    fn offset_micros(&self, instant: Instant) -> u64 {
+        instant.saturating_duration_since(self.run_start).as_micros() as u64
+    }
+
+    fn error(&self, e: io::Error) -> LoadGenError {
+        LoadGenError::OutputFileError(self.path.clone(), e)
+    }
+}
+
+// None of the values contain commas or quotes, so they don't need escaping.
+fn csv_field<T: ToString>(value: Option<T>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
\ No newline at end of file
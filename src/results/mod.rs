@@ -1,26 +1,726 @@
-use crate::errors::LoadGenError;
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
 
-pub async fn process_results(mut result_durations: Vec<u128>, mut result_errors: Vec<u16>) -> Result<(), LoadGenError> {
-    if result_durations.is_empty() || result_errors.is_empty() {
+use hdrhistogram::Histogram;
+use hyper::Version;
+use time::OffsetDateTime;
+use tokio::sync::mpsc::Receiver;
+use tokio::task::JoinHandle;
+use tokio::time::{interval, sleep_until, Instant, MissedTickBehavior};
+
+use crate::connection::ConnectionCounters;
+use crate::console::diagnostic;
+use crate::core::{PhaseTimings, RequestCounters, RequestOutcome, RequestResult, RequestTiming};
+use crate::errors::{LoadGenError, TransportErrorKind};
+use crate::progress::ProgressReporter;
+use crate::raw_log::RawLog;
+use crate::summary::{ClientSummary, ConnectionSummary, HistogramSummary, InFlightSummary, PhaseSummary, ProtocolSummary, RequestCounts, RunConfig, RunSummary, SchedulingLagSummary, StageSummary};
+
+// Highest trackable value in microseconds (one hour). Anything slower is clamped to this value.
+const MAX_TRACKABLE_MICROS: u64 = 60 * 60 * 1_000_000;
+const SIGNIFICANT_FIGURES: u8 = 3;
+const PERCENTILES: [f64; 7] = [50.0, 75.0, 90.0, 95.0, 99.0, 99.9, 99.99];
+// How often the in-flight count is sampled, and how many samples make up one point of its time series.
+const IN_FLIGHT_SAMPLE_PERIOD: Duration = Duration::from_millis(100);
+const IN_FLIGHT_SAMPLES_PER_POINT: u64 = 10;
+/// Results that can be queued up for the collector. Once full, requests wait to hand over their result,
+/// so memory stays bounded even when the collector falls behind.
+pub const RESULT_CHANNEL_CAPACITY: usize = 64 * 1024;
+
+/// Everything aggregated from the results of a run.
+pub struct RunResults {
+    histograms: LatencyHistograms,
+    outcome_counts: OutcomeCounts,
+    in_flight: InFlightStats,
+    stages: BTreeMap<usize, StageStats>,
+    connections: ConnectionStats,
+    // How late the runtime woke up the lag probe, in microseconds.
+    scheduling_lag: Option<Histogram<u64>>,
+    late: u64,
+    // When requests were intended to start (scheduled ones, including missed), and when they were actually sent.
+    intended_starts: Option<Span>,
+    send_times: Option<Span>,
+    run_start: Instant,
+    // Wall clock times, for the summary. Durations are measured on the monotonic clock.
+    started_at: OffsetDateTime,
+    ended_at: OffsetDateTime,
+    elapsed: Duration,
+}
+
+/// The first and last of a series of instants, which may arrive out of order.
+#[derive(Debug, Clone, Copy)]
+struct Span {
+    first: Instant,
+    last: Instant,
+}
+
+impl Span {
+    fn include(span: &mut Option<Span>, instant: Instant) {
+        *span = Some(match *span {
+            Some(Span { first, last }) => Span { first: first.min(instant), last: last.max(instant) },
+            None => Span { first: instant, last: instant },
+        });
+    }
+
+    /// Rate of `count` events spread from the first to the last instant, e.g. 2 events 1s apart is 1 per second.
+    /// Zero if there is no time between them to measure a rate over.
+    fn rate(span: Option<Span>, count: u64) -> f64 {
+        match span {
+            Some(Span { first, last }) if count > 1 && last > first => (count - 1) as f64 / last.duration_since(first).as_secs_f64(),
+            _ => 0f64,
+        }
+    }
+}
+
+/// Connections opened by the connector, and how many responses came over new vs reused (pooled) connections.
+#[derive(Default)]
+struct ConnectionStats {
+    opened: u64,
+    failed: u64,
+    closed: u64,
+    closed_by_peer: u64,
+    mean_connect_micros: f64,
+    responses_on_new: u64,
+    responses_on_reused: u64,
+}
+
+/// Results of a single rate profile stage, to see at which stage latency breaks down.
+struct StageStats {
+    outcome_counts: OutcomeCounts,
+    latency: Histogram<u64>,
+}
+
+impl RunResults {
+    pub fn new() -> Self {
+        RunResults {
+            histograms: LatencyHistograms::new(),
+            outcome_counts: OutcomeCounts::new(),
+            in_flight: InFlightStats::new(),
+            stages: BTreeMap::new(),
+            connections: ConnectionStats::default(),
+            scheduling_lag: None,
+            late: 0,
+            intended_starts: None,
+            send_times: None,
+            run_start: Instant::now(),
+            started_at: OffsetDateTime::now_utc(),
+            ended_at: OffsetDateTime::now_utc(),
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    pub fn set_scheduling_lag(&mut self, scheduling_lag: Histogram<u64>) {
+        self.scheduling_lag = Some(scheduling_lag);
+    }
+
+    pub fn record(&mut self, result: RequestResult) {
+        let stage = self.stages.entry(result.stage).or_insert_with(|| StageStats {
+            outcome_counts: OutcomeCounts::new(),
+            latency: new_histogram(),
+        });
+        stage.outcome_counts.record(result.outcome);
+        self.outcome_counts.record(result.outcome);
+        if result.late {
+            self.late += 1;
+        }
+        Span::include(&mut self.intended_starts, result.intended_start);
+        if let Some(send_time) = result.send_time {
+            Span::include(&mut self.send_times, send_time);
+        }
+        if let Some(timing) = result.timing {
+            if timing.reused_connection {
+                self.connections.responses_on_reused += 1;
+            } else {
+                self.connections.responses_on_new += 1;
+            }
+            stage.latency.saturating_record(timing.latency_micros);
+            self.histograms.record(timing);
+        }
+    }
+}
+
+impl Default for RunResults {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Spawns a background task that aggregates results as they arrive, so nothing is buffered until the end of the run.
+/// The task finishes once every sender has been dropped, or early once the run deadline (if any) passes.
+/// If given, the progress reporter is fed every result and prints interval statistics on its own period,
+/// and the raw log is streamed one row per result.
+/// Results already received when the deadline passes are still collected, only requests still in flight are left out.
+pub fn spawn_collector(
+    mut rx_results: Receiver<RequestResult>,
+    run_deadline: Option<Instant>,
+    counters: Arc<RequestCounters>,
+    connection_counters: Arc<ConnectionCounters>,
+    mut progress: Option<ProgressReporter>,
+    mut raw_log: Option<RawLog>) -> JoinHandle<RunResults> {
+    tokio::spawn(async move {
+        let mut results = RunResults::new();
+        let mut in_flight_sampler = interval(IN_FLIGHT_SAMPLE_PERIOD);
+        in_flight_sampler.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                result = rx_results.recv() => match result {
+                    Some(result) => collect(result, &mut results, &mut progress, &mut raw_log).await,
+                    None => break,
+                },
+                _ = wait_for_deadline(run_deadline) => {
+                    while let Ok(result) = rx_results.try_recv() {
+                        collect(result, &mut results, &mut progress, &mut raw_log).await;
+                    }
+                    diagnostic(format_args!("Run deadline reached, reporting on {} results...", results.outcome_counts.len()));
+                    break;
+                }
+                _ = wait_for_progress_tick(&mut progress) => {
+                    if let Some(progress) = progress.as_mut() {
+                        progress.report();
+                    }
+                }
+                _ = in_flight_sampler.tick() => results.in_flight.sample(counters.in_flight()),
+            }
+        }
+        // Waits for the rows still queued for the raw log to be written.
+        close_raw_log(raw_log).await;
+        results.in_flight.max = counters.max_in_flight();
+        results.connections.opened = connection_counters.opened();
+        results.connections.failed = connection_counters.failed();
+        results.connections.closed = connection_counters.closed();
+        results.connections.closed_by_peer = connection_counters.closed_by_peer();
+        results.connections.mean_connect_micros = connection_counters.mean_connect_micros();
+        results.elapsed = results.run_start.elapsed();
+        results.ended_at = OffsetDateTime::now_utc();
+        results
+    })
+}
+
+/// Samples of the number of requests in flight (sent, but body not yet fully collected).
+/// The mean is over every sample, the time series holds one averaged point per `IN_FLIGHT_SAMPLES_PER_POINT` samples.
+pub struct InFlightStats {
+    samples: u64,
+    sum: u64,
+    max: u64,
+    point_samples: u64,
+    point_sum: u64,
+    time_series: Vec<f64>,
+}
+
+impl InFlightStats {
+    fn new() -> Self {
+        InFlightStats {
+            samples: 0,
+            sum: 0,
+            max: 0,
+            point_samples: 0,
+            point_sum: 0,
+            time_series: vec![],
+        }
+    }
+
+    fn sample(&mut self, in_flight: u64) {
+        self.samples += 1;
+        self.sum += in_flight;
+        self.point_samples += 1;
+        self.point_sum += in_flight;
+        if self.point_samples == IN_FLIGHT_SAMPLES_PER_POINT {
+            self.time_series.push(self.point_sum as f64 / self.point_samples as f64);
+            self.point_samples = 0;
+            self.point_sum = 0;
+        }
+    }
+
+    fn mean(&self) -> f64 {
+        if self.samples == 0 {
+            return 0f64;
+        }
+        self.sum as f64 / self.samples as f64
+    }
+}
+
+async fn collect(result: RequestResult, results: &mut RunResults, progress: &mut Option<ProgressReporter>, raw_log: &mut Option<RawLog>) {
+    if let Some(progress) = progress.as_mut() {
+        progress.record(&result);
+    }
+    if let Some(log) = raw_log.as_ref() {
+        if !log.write(result).await {
+            // The writer stops at its first error. Stop logging rather than failing the whole run.
+            close_raw_log(raw_log.take()).await;
+        }
+    }
+    results.record(result);
+}
+
+async fn close_raw_log(raw_log: Option<RawLog>) {
+    if let Some(raw_log) = raw_log {
+        if let Err(e) = raw_log.finish().await {
+            diagnostic(&e);
+        }
+    }
+}
+
+async fn wait_for_deadline(run_deadline: Option<Instant>) {
+    match run_deadline {
+        Some(deadline) => sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn wait_for_progress_tick(progress: &mut Option<ProgressReporter>) {
+    match progress {
+        Some(progress) => progress.tick().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// High-dynamic-range histograms for latency and service time, per request phase and latency per negotiated protocol version.
+/// Their memory footprint is fixed by the bounds above, regardless of how many requests are recorded.
+pub struct LatencyHistograms {
+    latency: Histogram<u64>,
+    service_time: Histogram<u64>,
+    // In the order of `PhaseTimings::NAMES`.
+    phases: Vec<Histogram<u64>>,
+    latency_by_version: BTreeMap<&'static str, Histogram<u64>>,
+    latency_by_client: BTreeMap<usize, ClientLatency>,
+}
+
+/// Latency of the responses received by one client of the `ClientSet`, and the connection(s) they came over.
+struct ClientLatency {
+    latency: Histogram<u64>,
+    // Local addresses, more than one if the client had to reconnect.
+    connections: BTreeSet<SocketAddr>,
+}
+
+impl LatencyHistograms {
+    pub fn new() -> Self {
+        LatencyHistograms {
+            latency: new_histogram(),
+            service_time: new_histogram(),
+            phases: PhaseTimings::NAMES.iter().map(|_| new_histogram()).collect(),
+            latency_by_version: BTreeMap::new(),
+            latency_by_client: BTreeMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, timing: RequestTiming) {
+        self.latency.saturating_record(timing.latency_micros);
+        self.service_time.saturating_record(timing.service_time_micros);
+        for (histogram, value) in self.phases.iter_mut().zip(timing.phases.values()) {
+            histogram.saturating_record(value);
+        }
+        self.latency_by_version.entry(version_label(timing.version))
+            .or_insert_with(new_histogram)
+            .saturating_record(timing.latency_micros);
+        let client = self.latency_by_client.entry(timing.client).or_insert_with(|| ClientLatency {
+            latency: new_histogram(),
+            connections: BTreeSet::new(),
+        });
+        client.latency.saturating_record(timing.latency_micros);
+        if let Some(connection) = timing.connection {
+            client.connections.insert(connection);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latency.is_empty()
+    }
+}
+
+impl Default for LatencyHistograms {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn new_histogram() -> Histogram<u64> {
+    Histogram::new_with_max(MAX_TRACKABLE_MICROS, SIGNIFICANT_FIGURES).expect("invalid histogram bounds!")
+}
+
+fn version_label(version: Version) -> &'static str {
+    match version {
+        Version::HTTP_09 => "HTTP/0.9",
+        Version::HTTP_10 => "HTTP/1.0",
+        Version::HTTP_11 => "HTTP/1.1",
+        Version::HTTP_2 => "HTTP/2",
+        Version::HTTP_3 => "HTTP/3",
+        _ => "unknown",
+    }
+}
+
+/// Number of outcomes seen per status code and per transport error category, plus requests that were never sent.
+/// Bounded by the number of distinct codes and categories, not by `total`.
+pub struct OutcomeCounts {
+    counts: BTreeMap<u16, u64>,
+    errors: BTreeMap<TransportErrorKind, u64>,
+    missed: u64,
+    total: u64,
+}
+
+impl OutcomeCounts {
+    pub fn new() -> Self {
+        OutcomeCounts {
+            counts: BTreeMap::new(),
+            errors: BTreeMap::new(),
+            missed: 0,
+            total: 0,
+        }
+    }
+
+    pub fn record(&mut self, outcome: RequestOutcome) {
+        match outcome {
+            RequestOutcome::Status(status) => *self.counts.entry(status).or_insert(0) += 1,
+            RequestOutcome::Error(kind) => *self.errors.entry(kind).or_insert(0) += 1,
+            // Missed requests were never sent, so they don't count towards `len`.
+            RequestOutcome::Missed => {
+                self.missed += 1;
+                return;
+            }
+        }
+        self.total += 1;
+    }
+
+    /// Number of requests that were actually sent.
+    pub fn len(&self) -> u64 {
+        self.total
+    }
+
+    /// Number of requests that were scheduled, whether or not they were sent.
+    fn scheduled(&self) -> u64 {
+        self.total + self.missed
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    /// Counts grouped by status class, i.e. 1xx to 5xx. Anything outside of 100-599 is grouped under `other`.
+    fn class_counts(&self) -> BTreeMap<String, u64> {
+        let mut classes: BTreeMap<String, u64> = (1..=5).map(|class| (format!("{}xx", class), 0)).collect();
+        for (status, count) in &self.counts {
+            let class = match status {
+                100..=599 => format!("{}xx", status / 100),
+                _ => "other".to_string(),
+            };
+            *classes.entry(class).or_insert(0) += count;
+        }
+        classes
+    }
+}
+
+impl Default for OutcomeCounts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What counts as a successful response.
+/// By default, anything outside of the 5xx range is a success.
+pub struct SuccessCriteria {
+    allowed_statuses: Vec<u16>,
+    fail_on_4xx: bool,
+}
+
+impl SuccessCriteria {
+    pub fn new(allowed_statuses: Vec<u16>, fail_on_4xx: bool) -> Self {
+        SuccessCriteria {
+            allowed_statuses,
+            fail_on_4xx,
+        }
+    }
+
+    /// Percentage of outcomes that count as a success. Transport errors are always failures.
+    fn success_rate(&self, outcome_counts: &OutcomeCounts) -> f64 {
+        if outcome_counts.is_empty() {
+            return 0f64;
+        }
+        let total_successes: u64 = outcome_counts.counts.iter()
+            .filter(|(status, _)| self.is_success(**status))
+            .map(|(_, count)| count)
+            .sum();
+        total_successes as f64 / outcome_counts.len() as f64 * 100f64
+    }
+
+    fn is_success(&self, status: u16) -> bool {
+        // An explicit list of allowed statuses takes precedence over the class-based checks.
+        if !self.allowed_statuses.is_empty() {
+            return self.allowed_statuses.contains(&status);
+        }
+        match status {
+            500..=599 => false,
+            400..=499 => !self.fail_on_4xx,
+            _ => true,
+        }
+    }
+}
+
+/// Summarises the aggregated results of a run, for printing as text or JSON.
+pub async fn process_results(results: RunResults, success_criteria: &SuccessCriteria, config: RunConfig) -> Result<RunSummary, LoadGenError> {
+    let RunResults { histograms, outcome_counts, in_flight, stages, connections, scheduling_lag, late, intended_starts, send_times, started_at, ended_at, elapsed, .. } = results;
+    // Even when nothing was sent, missed requests show the load generator itself was the bottleneck.
+    if outcome_counts.scheduled() == 0 {
         return Err(LoadGenError::NoResultsError);
     }
-    result_durations.sort_unstable();
-    result_errors.sort_unstable();
 
-    let mut total_5xx_responses = 0;
-    result_errors.iter().for_each(|value| {
-        if value > &499_u16 && value < &599_u16 {
-            total_5xx_responses += 1;
+    // Intended vs achieved rate: when they diverge, the load generator itself is the bottleneck and not the service.
+    // The intended rate is over the schedule and the achieved rate over the actual sends, so requests sent late
+    // stretch the latter. Neither includes waiting for the last responses.
+    let intended_rps = Span::rate(intended_starts, outcome_counts.scheduled());
+    let achieved_rps = Span::rate(send_times, outcome_counts.len());
+
+    // Latency (from the intended send time) and service time (from the actual send time) are summarised separately.
+    let (latency, service_time) = if histograms.is_empty() {
+        (None, None)
+    } else {
+        (Some(summarise_histogram(&histograms.latency).await), Some(summarise_histogram(&histograms.service_time).await))
+    };
+    // Each phase of a request separately, e.g. to tell a slow header response from a slow streaming body.
+    let mut phases = vec![];
+    if !histograms.is_empty() {
+        for (phase, histogram) in PhaseTimings::NAMES.iter().zip(&histograms.phases) {
+            phases.push(PhaseSummary { phase: *phase, timing: summarise_histogram(histogram).await });
+        }
+    }
+    let mut protocols = BTreeMap::new();
+    for (version, histogram) in &histograms.latency_by_version {
+        protocols.insert(*version, ProtocolSummary {
+            responses: histogram.len(),
+            p50_ms: format_duration_as_millis(histogram.value_at_percentile(50.0) as f64).await,
+            p99_ms: format_duration_as_millis(histogram.value_at_percentile(99.0) as f64).await,
+        });
+    }
+
+    // Self-check: when the runtime wakes tasks up late, every timing above is inflated by the generator itself.
+    let scheduling_lag = match scheduling_lag {
+        Some(scheduling_lag) if !scheduling_lag.is_empty() => {
+            let timing = summarise_histogram(&scheduling_lag).await;
+            let exceeded = timing.percentiles_ms.get("p99").is_some_and(|p99| *p99 > config.max_scheduling_lag_ms);
+            Some(SchedulingLagSummary { timing, threshold_ms: config.max_scheduling_lag_ms, exceeded })
+        }
+        _ => None,
+    };
+
+    // Per connection (client), to see whether load is spread evenly over backends. A single client is the whole run.
+    let mut clients = vec![];
+    if config.connections > 1 {
+        for (index, client) in &histograms.latency_by_client {
+            clients.push(ClientSummary {
+                client: *index,
+                connections: client.connections.iter().map(SocketAddr::to_string).collect(),
+                responses: client.latency.len(),
+                p50_ms: format_duration_as_millis(client.latency.value_at_percentile(50.0) as f64).await,
+                p99_ms: format_duration_as_millis(client.latency.value_at_percentile(99.0) as f64).await,
+            });
+        }
+    }
+
+    // Little's law: the mean number of requests in flight should match throughput multiplied by the mean service time.
+    let in_flight = InFlightSummary {
+        mean: in_flight.mean(),
+        max: in_flight.max,
+        expected_by_littles_law: achieved_rps * histograms.service_time.mean() / 1_000_000f64,
+        per_second: in_flight.time_series,
+    };
+
+    // A single stage is the whole run, which is summarised already.
+    let mut stage_summaries = vec![];
+    if config.stages.len() > 1 {
+        for (index, stage) in &stages {
+            stage_summaries.push(StageSummary {
+                label: config.stages.get(*index).cloned().unwrap_or_else(|| "unknown".to_string()),
+                requests: stage.outcome_counts.len(),
+                success_rate: success_criteria.success_rate(&stage.outcome_counts),
+                p50_ms: format_duration_as_millis(stage.latency.value_at_percentile(50.0) as f64).await,
+                p99_ms: format_duration_as_millis(stage.latency.value_at_percentile(99.0) as f64).await,
+            });
         }
-    });
-    let median = result_durations.len() / 2;
-    let success_rate: f32 = ((1 - (total_5xx_responses / result_errors.len())) * 100) as f32;
-    println!("success: {:.2} %", success_rate);
-    let formatted_p50 = format_duration_as_seconds(result_durations[median]).await;
-    println!("median: {}s", formatted_p50);
-    Ok(())
+    }
+
+    Ok(RunSummary {
+        config,
+        started_at,
+        ended_at,
+        elapsed_secs: elapsed.as_secs_f64(),
+        counts: RequestCounts {
+            scheduled: outcome_counts.scheduled(),
+            sent: outcome_counts.len(),
+            late,
+            missed: outcome_counts.missed,
+        },
+        success_rate: success_criteria.success_rate(&outcome_counts),
+        status_classes: outcome_counts.class_counts(),
+        errors: outcome_counts.errors.iter().map(|(kind, count)| (kind.to_string(), *count)).collect(),
+        status_codes: outcome_counts.counts,
+        intended_rps,
+        achieved_rps,
+        latency,
+        service_time,
+        phases,
+        protocols,
+        clients,
+        scheduling_lag,
+        in_flight,
+        connections: ConnectionSummary {
+            opened: connections.opened,
+            failed: connections.failed,
+            closed: connections.closed,
+            closed_by_peer: connections.closed_by_peer,
+            mean_connect_ms: format_duration_as_millis(connections.mean_connect_micros).await,
+            responses_on_new: connections.responses_on_new,
+            responses_on_reused: connections.responses_on_reused,
+        },
+        stages: stage_summaries,
+    })
 }
 
-async fn format_duration_as_seconds(duration: u128) -> f32 {
-    duration as f32 / 1000f32
-}
\ No newline at end of file
+async fn summarise_histogram(histogram: &Histogram<u64>) -> HistogramSummary {
+    let mut percentiles_ms = BTreeMap::new();
+    for percentile in PERCENTILES {
+        let value = histogram.value_at_percentile(percentile);
+        percentiles_ms.insert(format!("p{}", percentile), format_duration_as_millis(value as f64).await);
+    }
+    HistogramSummary {
+        samples: histogram.len(),
+        min_ms: format_duration_as_millis(histogram.min() as f64).await,
+        mean_ms: format_duration_as_millis(histogram.mean()).await,
+        stddev_ms: format_duration_as_millis(histogram.stdev()).await,
+        max_ms: format_duration_as_millis(histogram.max() as f64).await,
+        percentiles_ms,
+    }
+}
+
+async fn format_duration_as_millis(duration_micros: f64) -> f64 {
+    duration_micros / 1000f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> RunConfig {
+        RunConfig {
+            target: "http://localhost:8080/".to_string(),
+            method: "GET".to_string(),
+            protocol: "http2".to_string(),
+            stages: vec![],
+            connections: 1,
+            concurrency: None,
+            arrival: Some("constant".to_string()),
+            seed: Some(0),
+            total: None,
+            duration_secs: None,
+            timeout_secs: 20f64,
+            workers: "1 workers".to_string(),
+            max_scheduling_lag_ms: 10f64,
+        }
+    }
+
+    fn result(outcome: RequestOutcome, intended_start: Instant, send_time: Option<Instant>) -> RequestResult {
+        RequestResult { outcome, stage: 0, late: false, intended_start, send_time, timing: None }
+    }
+
+    fn outcome_counts(outcomes: &[(RequestOutcome, u64)]) -> OutcomeCounts {
+        let mut outcome_counts = OutcomeCounts::new();
+        for (outcome, count) in outcomes {
+            for _ in 0..*count {
+                outcome_counts.record(*outcome);
+            }
+        }
+        outcome_counts
+    }
+
+    #[test]
+    fn computes_fractional_success_rates() {
+        let outcome_counts = outcome_counts(&[(RequestOutcome::Status(200), 3), (RequestOutcome::Status(503), 1)]);
+        assert_eq!(SuccessCriteria::new(vec![], false).success_rate(&outcome_counts), 75f64);
+    }
+
+    #[test]
+    fn counts_every_5xx_as_a_failure() {
+        let criteria = SuccessCriteria::new(vec![], false);
+        assert!(!criteria.is_success(500));
+        assert!(!criteria.is_success(599));
+        assert!(criteria.is_success(404));
+        assert!(criteria.is_success(600));
+        let outcome_counts = outcome_counts(&[(RequestOutcome::Status(599), 1)]);
+        assert_eq!(outcome_counts.class_counts()["5xx"], 1);
+    }
+
+    #[test]
+    fn fails_4xx_when_asked_to() {
+        let criteria = SuccessCriteria::new(vec![], true);
+        assert!(!criteria.is_success(404));
+        assert!(!criteria.is_success(500));
+        assert!(criteria.is_success(302));
+    }
+
+    #[test]
+    fn allowed_statuses_take_precedence() {
+        let criteria = SuccessCriteria::new(vec![201, 503], true);
+        assert!(criteria.is_success(201));
+        assert!(criteria.is_success(503));
+        assert!(!criteria.is_success(200));
+    }
+
+    #[test]
+    fn counts_transport_errors_as_failures() {
+        let outcome_counts = outcome_counts(&[
+            (RequestOutcome::Status(200), 1),
+            (RequestOutcome::Error(TransportErrorKind::Connect), 1),
+            // Never sent, so neither a success nor a failure.
+            (RequestOutcome::Missed, 2),
+        ]);
+        assert_eq!(SuccessCriteria::new(vec![], false).success_rate(&outcome_counts), 50f64);
+    }
+
+    #[test]
+    fn groups_unknown_statuses_as_other() {
+        let outcome_counts = outcome_counts(&[(RequestOutcome::Status(99), 1), (RequestOutcome::Status(600), 2), (RequestOutcome::Status(204), 1)]);
+        let classes = outcome_counts.class_counts();
+        assert_eq!(classes["other"], 3);
+        assert_eq!(classes["2xx"], 1);
+        // Every class is listed, even without any responses in it.
+        assert_eq!(classes["1xx"], 0);
+    }
+
+    #[tokio::test]
+    async fn measures_rates_over_the_schedule_and_the_sends() {
+        let run_start = Instant::now();
+        let mut results = RunResults::new();
+        // 11 requests scheduled 100ms apart (10 rps), but sent 200ms apart (5 rps) as the generator fell behind.
+        for index in 0..=10 {
+            let intended_start = run_start + Duration::from_millis(100 * index);
+            let send_time = run_start + Duration::from_millis(200 * index);
+            results.record(result(RequestOutcome::Status(200), intended_start, Some(send_time)));
+        }
+        // Waiting on slow responses after the last send doesn't dilute either rate.
+        results.elapsed = Duration::from_secs(10);
+        let summary = process_results(results, &SuccessCriteria::new(vec![], false), config()).await.unwrap();
+        assert!((summary.intended_rps - 10f64).abs() < 1e-6, "{}", summary.intended_rps);
+        assert!((summary.achieved_rps - 5f64).abs() < 1e-6, "{}", summary.achieved_rps);
+    }
+
+    #[tokio::test]
+    async fn reports_runs_where_every_request_was_missed() {
+        let run_start = Instant::now();
+        let mut results = RunResults::new();
+        for index in 0..10 {
+            results.record(result(RequestOutcome::Missed, run_start + Duration::from_millis(100 * index), None));
+        }
+        let summary = process_results(results, &SuccessCriteria::new(vec![], false), config()).await.unwrap();
+        assert_eq!(summary.counts.scheduled, 10);
+        assert_eq!(summary.counts.missed, 10);
+        assert_eq!(summary.counts.sent, 0);
+        assert_eq!(summary.achieved_rps, 0f64);
+        assert!(summary.latency.is_none());
+    }
+
+    #[tokio::test]
+    async fn has_nothing_to_report_without_any_scheduled_request() {
+        let results = RunResults::new();
+        let summary = process_results(results, &SuccessCriteria::new(vec![], false), config()).await;
+        assert!(matches!(summary, Err(LoadGenError::NoResultsError)));
+    }
+}
@@ -0,0 +1,102 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use core_affinity::CoreId;
+use hdrhistogram::Histogram;
+use tokio::runtime::{Builder, Runtime};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::{interval, Instant, MissedTickBehavior};
+
+use crate::console::diagnostic;
+use crate::errors::LoadGenError;
+
+// How often the lag probe checks how late it is woken up.
+const LAG_PROBE_PERIOD: Duration = Duration::from_millis(10);
+// Highest trackable lag in microseconds (one minute).
+const MAX_TRACKABLE_LAG_MICROS: u64 = 60 * 1_000_000;
+
+/// How the Tokio runtime running the load generator is built.
+pub struct RuntimeOptions {
+    // Worker threads of the multi-threaded runtime. Tokio defaults to one per core.
+    pub workers: Option<usize>,
+    // Run everything on the main thread, with a current-thread runtime.
+    pub single_thread: bool,
+    // Cores to pin the runtime's threads to, assigned in turn as threads start.
+    pub cpu_affinity: Vec<usize>,
+}
+
+/// Builds the runtime explicitly, so its threads can be tuned for the load profile e.g. 10K vs 100K rps.
+pub fn build_runtime(options: &RuntimeOptions) -> Result<Runtime, LoadGenError> {
+    let core_ids = resolve_core_ids(&options.cpu_affinity)?;
+    let mut builder = if options.single_thread {
+        // The current-thread runtime runs on the main thread, which is pinned right away.
+        if let Some(core_id) = core_ids.first() {
+            pin_current_thread(*core_id);
+        }
+        Builder::new_current_thread()
+    } else {
+        let mut builder = Builder::new_multi_thread();
+        if let Some(workers) = options.workers {
+            builder.worker_threads(workers);
+        }
+        if !core_ids.is_empty() {
+            let next_core = AtomicUsize::new(0);
+            builder.on_thread_start(move || {
+                let index = next_core.fetch_add(1, Ordering::Relaxed) % core_ids.len();
+                pin_current_thread(core_ids[index]);
+            });
+        }
+        builder
+    };
+    builder.enable_all().build().map_err(LoadGenError::RuntimeError)
+}
+
+fn resolve_core_ids(cpus: &[usize]) -> Result<Vec<CoreId>, LoadGenError> {
+    if cpus.is_empty() {
+        return Ok(vec![]);
+    }
+    let available = core_affinity::get_core_ids().unwrap_or_default();
+    cpus.iter()
+        .map(|cpu| available.iter().find(|core_id| core_id.id == *cpu).copied().ok_or(LoadGenError::InvalidCpuError(*cpu)))
+        .collect()
+}
+
+fn pin_current_thread(core_id: CoreId) {
+    if !core_affinity::set_for_current(core_id) {
+        diagnostic(format_args!("Failed to pin a runtime thread to CPU {}", core_id.id));
+    }
+}
+
+/// Self-check of the load generator: a task that should wake up every `LAG_PROBE_PERIOD` records how late it is.
+/// A busy executor delays every task alike, so a high lag means the generator's own numbers can't be trusted.
+pub struct LagProbe {
+    stop: watch::Sender<bool>,
+    handle: JoinHandle<Histogram<u64>>,
+}
+
+impl LagProbe {
+    pub fn spawn() -> Self {
+        let (stop, mut stopped) = watch::channel(false);
+        let handle = tokio::spawn(async move {
+            let mut lag = Histogram::new_with_max(MAX_TRACKABLE_LAG_MICROS, 3).expect("invalid histogram bounds!");
+            let mut probe = interval(LAG_PROBE_PERIOD);
+            probe.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    // Resolves with the time the tick was due, so the difference to now is how late we were woken up.
+                    due = probe.tick() => lag.saturating_record(Instant::now().duration_since(due).as_micros() as u64),
+                    _ = stopped.changed() => break,
+                }
+            }
+            lag
+        });
+        LagProbe { stop, handle }
+    }
+
+    /// Stops the probe and returns the lag it recorded, in microseconds.
+    pub async fn finish(self) -> Result<Histogram<u64>, tokio::task::JoinError> {
+        let _ = self.stop.send(true);
+        self.handle.await
+    }
+}
\ No newline at end of file
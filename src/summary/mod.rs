@@ -0,0 +1,280 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::io;
+
+use clap::ValueEnum;
+use serde::Serialize;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use crate::errors::LoadGenError;
+
+/// Format of the final report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable report.
+    Text,
+    /// Machine-readable summary, e.g. for CI.
+    Json,
+}
+
+/// Structured summary of a run, printed as text or serialized as JSON.
+#[derive(Debug, Serialize)]
+pub struct RunSummary {
+    pub config: RunConfig,
+    #[serde(with = "time::serde::rfc3339")]
+    pub started_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub ended_at: OffsetDateTime,
+    pub elapsed_secs: f64,
+    pub counts: RequestCounts,
+    pub success_rate: f64,
+    pub status_codes: BTreeMap<u16, u64>,
+    pub status_classes: BTreeMap<String, u64>,
+    pub errors: BTreeMap<String, u64>,
+    // From the first to the last intended send time, and from the first to the last actual send.
+    pub intended_rps: f64,
+    pub achieved_rps: f64,
+    // Only present once at least one response came back.
+    pub latency: Option<HistogramSummary>,
+    pub service_time: Option<HistogramSummary>,
+    // Consecutive phases of a request, adding up to its latency. Empty without any responses.
+    pub phases: Vec<PhaseSummary>,
+    pub protocols: BTreeMap<&'static str, ProtocolSummary>,
+    // Only with more than one connection (`--connections`).
+    pub clients: Vec<ClientSummary>,
+    pub scheduling_lag: Option<SchedulingLagSummary>,
+    pub in_flight: InFlightSummary,
+    pub connections: ConnectionSummary,
+    pub stages: Vec<StageSummary>,
+}
+
+/// The parameters a run was started with.
+#[derive(Debug, Serialize)]
+pub struct RunConfig {
+    pub target: String,
+    pub method: String,
+    pub protocol: String,
+    // Labels of the rate profile stages, empty in closed-loop mode.
+    pub stages: Vec<String>,
+    pub connections: usize,
+    pub concurrency: Option<u32>,
+    pub arrival: Option<String>,
+    pub seed: Option<u64>,
+    pub total: Option<u32>,
+    pub duration_secs: Option<f64>,
+    pub timeout_secs: f64,
+    pub workers: String,
+    pub max_scheduling_lag_ms: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RequestCounts {
+    pub scheduled: u64,
+    pub sent: u64,
+    pub late: u64,
+    pub missed: u64,
+}
+
+/// Latency distribution in milliseconds. Percentiles are keyed by name e.g. `p99.9`.
+#[derive(Debug, Serialize)]
+pub struct HistogramSummary {
+    pub samples: u64,
+    pub min_ms: f64,
+    pub mean_ms: f64,
+    pub stddev_ms: f64,
+    pub max_ms: f64,
+    pub percentiles_ms: BTreeMap<String, f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PhaseSummary {
+    pub phase: &'static str,
+    #[serde(flatten)]
+    pub timing: HistogramSummary,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProtocolSummary {
+    pub responses: u64,
+    pub p50_ms: f64,
+    pub p99_ms: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClientSummary {
+    pub client: usize,
+    // Local addresses of the connection(s) the client used.
+    pub connections: Vec<String>,
+    pub responses: u64,
+    pub p50_ms: f64,
+    pub p99_ms: f64,
+}
+
+/// How late the runtime woke up the lag probe. Above the threshold (at p99), the generator's own numbers can't be trusted.
+#[derive(Debug, Serialize)]
+pub struct SchedulingLagSummary {
+    #[serde(flatten)]
+    pub timing: HistogramSummary,
+    pub threshold_ms: f64,
+    pub exceeded: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InFlightSummary {
+    pub mean: f64,
+    pub max: u64,
+    pub expected_by_littles_law: f64,
+    pub per_second: Vec<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConnectionSummary {
+    pub opened: u64,
+    pub failed: u64,
+    pub closed: u64,
+    pub closed_by_peer: u64,
+    pub mean_connect_ms: f64,
+    // Responses received over a connection established for them vs over a pooled connection that was already open.
+    pub responses_on_new: u64,
+    pub responses_on_reused: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StageSummary {
+    pub label: String,
+    pub requests: u64,
+    pub success_rate: f64,
+    pub p50_ms: f64,
+    pub p99_ms: f64,
+}
+
+/// Writes the summary in the given format, to `output_file` if given or to stdout otherwise.
+pub fn write_summary(summary: &RunSummary, format: OutputFormat, output_file: Option<&str>) -> Result<(), LoadGenError> {
+    let report = match format {
+        OutputFormat::Text => summary.to_string(),
+        OutputFormat::Json => serde_json::to_string_pretty(summary)
+            .map_err(|e| LoadGenError::OutputFileError(output_file.unwrap_or("stdout").to_string(), io::Error::from(e)))?,
+    };
+    match output_file {
+        Some(path) => std::fs::write(path, report).map_err(|e| LoadGenError::OutputFileError(path.to_string(), e)),
+        None => {
+            println!("{}", report);
+            Ok(())
+        }
+    }
+}
+
+impl Display for RunSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "started: {}", self.started_at.format(&Rfc3339).unwrap_or_default())?;
+        writeln!(f, "ended: {}", self.ended_at.format(&Rfc3339).unwrap_or_default())?;
+        writeln!(f, "status codes:")?;
+        for (status, count) in &self.status_codes {
+            writeln!(f, "  {}: {}", status, count)?;
+        }
+        writeln!(f, "status classes:")?;
+        for (class, count) in &self.status_classes {
+            writeln!(f, "  {}: {}", class, count)?;
+        }
+        if !self.errors.is_empty() {
+            writeln!(f, "transport errors:")?;
+            for (kind, count) in &self.errors {
+                writeln!(f, "  {}: {}", kind, count)?;
+            }
+        }
+
+        writeln!(f, "success: {:.2} %", self.success_rate)?;
+        writeln!(f, "throughput: {:.2} rps, run took {:.2}s", self.achieved_rps, self.elapsed_secs)?;
+
+        // Intended vs achieved rate: when they diverge, the load generator itself is the bottleneck and not the service.
+        writeln!(f, "schedule:")?;
+        writeln!(f, "  scheduled: {} (intended rate: {:.2} rps)", self.counts.scheduled, self.intended_rps)?;
+        writeln!(f, "  sent: {} (achieved rate: {:.2} rps)", self.counts.sent, self.achieved_rps)?;
+        writeln!(f, "  sent late: {}", self.counts.late)?;
+        writeln!(f, "  missed: {}", self.counts.missed)?;
+        if self.counts.missed > 0 || self.counts.late > 0 {
+            writeln!(f, "  WARNING: the load generator fell behind its schedule, it may be the bottleneck!")?;
+        }
+        if let Some(scheduling_lag) = &self.scheduling_lag {
+            writeln!(f, "scheduling lag ({} runtime): p50: {:.3}ms, p99: {:.3}ms, max: {:.3}ms",
+                     self.config.workers,
+                     scheduling_lag.timing.percentiles_ms.get("p50").copied().unwrap_or_default(),
+                     scheduling_lag.timing.percentiles_ms.get("p99").copied().unwrap_or_default(),
+                     scheduling_lag.timing.max_ms)?;
+            if scheduling_lag.exceeded {
+                writeln!(f, "  WARNING: the runtime's scheduling lag is above {:.3}ms, latencies are inflated by the load generator itself!",
+                         scheduling_lag.threshold_ms)?;
+            }
+        }
+
+        // Latency (from the intended send time) and service time (from the actual send time) are reported separately.
+        // A large gap between the two means requests were queued behind a backed-up executor.
+        let (Some(latency), Some(service_time)) = (&self.latency, &self.service_time) else {
+            return Ok(());
+        };
+        write_histogram(f, "latency", latency)?;
+        write_histogram(f, "service time", service_time)?;
+        for phase in &self.phases {
+            write_histogram(f, &format!("{} phase", phase.phase), &phase.timing)?;
+        }
+        // Compares responses across protocol versions e.g. when `--auto` talks to a mixed fleet.
+        writeln!(f, "protocols:")?;
+        for (version, protocol) in &self.protocols {
+            writeln!(f, "  {}: {} responses, p50: {:.3}ms, p99: {:.3}ms", version, protocol.responses, protocol.p50_ms, protocol.p99_ms)?;
+        }
+        if !self.clients.is_empty() {
+            writeln!(f, "per connection:")?;
+            for client in &self.clients {
+                writeln!(f, "  #{} [{}]: {} responses, p50: {:.3}ms, p99: {:.3}ms",
+                         client.client,
+                         client.connections.join(", "),
+                         client.responses,
+                         client.p50_ms,
+                         client.p99_ms)?;
+            }
+        }
+
+        writeln!(f, "in flight:")?;
+        writeln!(f, "  mean: {:.2}", self.in_flight.mean)?;
+        writeln!(f, "  max: {}", self.in_flight.max)?;
+        writeln!(f, "  expected by Little's law: {:.2} ({:.1} rps x {:.3}ms mean service time)",
+                 self.in_flight.expected_by_littles_law,
+                 self.achieved_rps,
+                 service_time.mean_ms)?;
+        let per_second: Vec<String> = self.in_flight.per_second.iter().map(|point| format!("{:.1}", point)).collect();
+        writeln!(f, "  per second: [{}]", per_second.join(", "))?;
+
+        writeln!(f, "connections:")?;
+        writeln!(f, "  opened: {} (mean connect time: {:.3}ms)", self.connections.opened, self.connections.mean_connect_ms)?;
+        writeln!(f, "  failed to connect: {}", self.connections.failed)?;
+        writeln!(f, "  closed: {} ({} by peer)", self.connections.closed, self.connections.closed_by_peer)?;
+        write!(f, "  responses: {} on new connections, {} on reused connections",
+               self.connections.responses_on_new,
+               self.connections.responses_on_reused)?;
+
+        if !self.stages.is_empty() {
+            write!(f, "\nstages:")?;
+            for stage in &self.stages {
+                write!(f, "\n  {}: {} requests, success: {:.2} %, p50: {:.3}ms, p99: {:.3}ms",
+                       stage.label,
+                       stage.requests,
+                       stage.success_rate,
+                       stage.p50_ms,
+                       stage.p99_ms)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_histogram(f: &mut std::fmt::Formatter<'_>, name: &str, histogram: &HistogramSummary) -> std::fmt::Result {
+    writeln!(f, "{} ({} samples):", name, histogram.samples)?;
+    writeln!(f, "  min: {:.3}ms", histogram.min_ms)?;
+    writeln!(f, "  mean: {:.3}ms", histogram.mean_ms)?;
+    writeln!(f, "  stddev: {:.3}ms", histogram.stddev_ms)?;
+    for (percentile, value) in &histogram.percentiles_ms {
+        writeln!(f, "  {}: {:.3}ms", percentile, value)?;
+    }
+    writeln!(f, "  max: {:.3}ms", histogram.max_ms)
+}
\ No newline at end of file
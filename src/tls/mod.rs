@@ -0,0 +1,237 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+
+use hyper_rustls::{FixedServerNameResolver, HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::client::legacy::connect::HttpConnector;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+
+use crate::core::ProtocolMode;
+use crate::errors::LoadGenError;
+
+/// TLS settings for `https://` targets. Plain `http://` targets ignore them.
+#[derive(Default)]
+pub struct TlsOptions {
+    pub ca_file: Option<String>,
+    pub client_cert_file: Option<String>,
+    pub client_key_file: Option<String>,
+    pub server_name: Option<String>,
+    pub insecure: bool,
+}
+
+/// Wraps the TCP connector so that `https://` targets are dialed over TLS, negotiating the protocol via ALPN.
+pub fn build_connector(mut http: HttpConnector, options: &TlsOptions, protocol_mode: ProtocolMode) -> Result<HttpsConnector<HttpConnector>, LoadGenError> {
+    // The HttpConnector refuses non-http schemes unless told otherwise, the TLS layer is wrapped around it.
+    http.enforce_http(false);
+    let config = build_tls_config(options)?;
+    let builder = HttpsConnectorBuilder::new()
+        .with_tls_config(config)
+        .https_or_http();
+    let builder = match &options.server_name {
+        Some(server_name) => {
+            let server_name = ServerName::try_from(server_name.clone())
+                .map_err(|_| LoadGenError::TlsConfigError(format!("{} is an invalid server name", server_name)))?;
+            builder.with_server_name_resolver(FixedServerNameResolver::new(server_name))
+        }
+        None => builder,
+    };
+    // The enabled versions become the ALPN protocols offered during the handshake.
+    let connector = match protocol_mode {
+        ProtocolMode::Http1 => builder.enable_http1().wrap_connector(http),
+        ProtocolMode::Http2 => builder.enable_http2().wrap_connector(http),
+        ProtocolMode::Auto => builder.enable_all_versions().wrap_connector(http),
+    };
+    Ok(connector)
+}
+
+fn build_tls_config(options: &TlsOptions) -> Result<ClientConfig, LoadGenError> {
+    let mut roots = RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    if let Some(ca_file) = &options.ca_file {
+        for cert in load_certs(ca_file)? {
+            roots.add(cert).map_err(|e| LoadGenError::TlsConfigError(format!("cannot add CA certificate from {} ({})", ca_file, e)))?;
+        }
+    }
+
+    let builder = ClientConfig::builder().with_root_certificates(roots);
+    let mut config = match (&options.client_cert_file, &options.client_key_file) {
+        (Some(cert_file), Some(key_file)) => builder
+            .with_client_auth_cert(load_certs(cert_file)?, load_private_key(key_file)?)
+            .map_err(|e| LoadGenError::TlsConfigError(format!("invalid client certificate ({})", e)))?,
+        _ => builder.with_no_client_auth(),
+    };
+
+    if options.insecure {
+        let provider = config.crypto_provider().clone();
+        config.dangerous().set_certificate_verifier(Arc::new(InsecureVerifier(provider)));
+    }
+    Ok(config)
+}
+
+fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>, LoadGenError> {
+    let file = File::open(path).map_err(|e| LoadGenError::TlsConfigError(format!("cannot open {} ({})", path, e)))?;
+    rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| LoadGenError::TlsConfigError(format!("cannot read certificates from {} ({})", path, e)))
+}
+
+fn load_private_key(path: &str) -> Result<PrivateKeyDer<'static>, LoadGenError> {
+    let file = File::open(path).map_err(|e| LoadGenError::TlsConfigError(format!("cannot open {} ({})", path, e)))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|e| LoadGenError::TlsConfigError(format!("cannot read private key from {} ({})", path, e)))?
+        .ok_or_else(|| LoadGenError::TlsConfigError(format!("no private key found in {}", path)))
+}
+
+// Accepts any server certificate (`--insecure`). Handshake signatures are still checked so the session itself is sound.
+#[derive(Debug)]
+struct InsecureVerifier(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for InsecureVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use hyper::Uri;
+    use hyper_util::client::legacy::connect::Connection;
+    use rustls::pki_types::PrivatePkcs8KeyDer;
+    use rustls::ServerConfig;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+    use tokio_rustls::TlsAcceptor;
+    use tower_service::Service;
+
+    use super::*;
+
+    // A self-signed certificate for `localhost`, also written out as PEM files named after the test.
+    struct TestCertificate {
+        cert: CertificateDer<'static>,
+        key: PrivateKeyDer<'static>,
+        cert_file: String,
+        key_file: String,
+    }
+
+    fn test_certificate(test: &str) -> TestCertificate {
+        let rcgen::CertifiedKey { cert, key_pair } = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        TestCertificate {
+            cert: cert.der().clone(),
+            key: PrivatePkcs8KeyDer::from(key_pair.serialize_der()).into(),
+            cert_file: temp_file(&format!("{}.crt", test), &cert.pem()),
+            key_file: temp_file(&format!("{}.key", test), &key_pair.serialize_pem()),
+        }
+    }
+
+    fn temp_file(name: &str, contents: &str) -> String {
+        let path = std::env::temp_dir().join(format!("toy-loadgen-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn options(ca_file: Option<String>, insecure: bool) -> TlsOptions {
+        TlsOptions {
+            ca_file,
+            // The server is dialed by IP, the certificate is for `localhost`.
+            server_name: Some("localhost".to_string()),
+            insecure,
+            ..TlsOptions::default()
+        }
+    }
+
+    // Completes TLS handshakes with the given certificate, offering h2 and HTTP/1.1 via ALPN.
+    async fn spawn_tls_server(certificate: &TestCertificate) -> SocketAddr {
+        let mut config = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(vec![certificate.cert.clone()], certificate.key.clone_key())
+            .unwrap();
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    // Holds the connection open until the client hangs up.
+                    if let Ok(mut stream) = acceptor.accept(stream).await {
+                        let _ = stream.read_to_end(&mut vec![]).await;
+                    }
+                });
+            }
+        });
+        address
+    }
+
+    // Dials the server over TLS. Returns whether h2 was negotiated.
+    async fn handshake(address: SocketAddr, options: &TlsOptions, protocol_mode: ProtocolMode) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let mut connector = build_connector(HttpConnector::new(), options, protocol_mode)?;
+        let uri: Uri = format!("https://{}/", address).parse()?;
+        let stream = connector.call(uri).await?;
+        Ok(stream.connected().is_negotiated_h2())
+    }
+
+    #[test]
+    fn reports_missing_files() {
+        assert!(matches!(load_certs("/nonexistent/ca.pem"), Err(LoadGenError::TlsConfigError(_))));
+        assert!(matches!(load_private_key("/nonexistent/client.key"), Err(LoadGenError::TlsConfigError(_))));
+    }
+
+    #[test]
+    fn reports_key_files_without_a_key() {
+        let certificate = test_certificate("no_key");
+        assert_eq!(load_certs(&certificate.cert_file).unwrap(), vec![certificate.cert.clone()]);
+        match load_private_key(&certificate.cert_file) {
+            Err(LoadGenError::TlsConfigError(reason)) => assert!(reason.starts_with("no private key found"), "{}", reason),
+            _ => panic!("a certificate is not a private key"),
+        }
+        assert!(load_private_key(&certificate.key_file).is_ok());
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_certificates() {
+        let certificate = test_certificate("unknown");
+        let address = spawn_tls_server(&certificate).await;
+        assert!(handshake(address, &options(None, false), ProtocolMode::Http2).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn trusts_the_given_ca_and_negotiates_h2() {
+        let certificate = test_certificate("cacert");
+        let address = spawn_tls_server(&certificate).await;
+        let options = options(Some(certificate.cert_file.clone()), false);
+        assert!(handshake(address, &options, ProtocolMode::Http2).await.unwrap());
+        // Only HTTP/1.1 is offered via ALPN.
+        assert!(!handshake(address, &options, ProtocolMode::Http1).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn accepts_any_certificate_when_insecure() {
+        let certificate = test_certificate("insecure");
+        let address = spawn_tls_server(&certificate).await;
+        assert!(handshake(address, &options(None, true), ProtocolMode::Auto).await.unwrap());
+    }
+}